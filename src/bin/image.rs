@@ -4,12 +4,15 @@ use image::io::Reader;
 use indexmap::IndexMap;
 use wfinfo::{
     database::Database,
-    ocr::{detect_theme, normalize_string, reward_image_to_reward_names},
+    ocr::{detect_theme, normalize_string, reward_image_to_reward_names, Frame},
     testing::Label,
+    utils::fetch_prices_and_items,
 };
 
 fn main() {
     let mut labels = IndexMap::new();
+    let (prices, filtered_items) =
+        fetch_prices_and_items().expect("Failed to fetch price and item data");
 
     for argument in std::env::args().skip(1) {
         let filepath = PathBuf::from(argument);
@@ -21,7 +24,7 @@ fn main() {
         let text: Vec<_> = detections.iter().map(|s| normalize_string(s)).collect();
         println!("{:#?}", text);
 
-        let db = Database::load_from_file(None, None);
+        let db = Database::load_from_file(Some(&prices), Some(&filtered_items));
         let items: Vec<_> = text.iter().map(|s| db.find_item(s, None)).collect();
         for item in items.iter() {
             if let Some(item) = item {
@@ -37,7 +40,7 @@ fn main() {
                     .unwrap_or_else(|| "ERROR".to_string())
             })
             .collect();
-        let theme = detect_theme(&image);
+        let theme = detect_theme(&Frame::new(image)).expect("No theme found");
         labels.insert(
             filepath
                 .file_name()
@@ -1,44 +1,71 @@
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{error::Error, str::FromStr};
-use std::{fs::File, thread};
+use std::{
+    fs::{read_to_string, File},
+    thread,
+};
 use std::{
     io::{BufRead, BufReader, Read, Seek, SeekFrom},
     sync::mpsc::channel,
 };
 use std::{path::PathBuf, sync::mpsc};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use env_logger::{Builder, Env};
 use global_hotkey::{hotkey::HotKey, GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
-use image::DynamicImage;
 use log::{debug, error, info, warn};
 use notify::{watcher, RecursiveMode, Watcher};
-use xcap::Window;
+use regex::Regex;
 
 use wfinfo::{
+    burst::{capture_burst, detect_burst_rewards, Burst, BurstSelection, MatchedReward},
+    capture::{
+        CaptureKind, CaptureRegion, CaptureSource, CroppedCapture, DirectoryCapture,
+        MonitorCapture, PortalCapture, ReaderCapture, WindowCapture, WindowMatcher,
+    },
+    config::Config,
     database::Database,
-    ocr::{normalize_string, reward_image_to_reward_names, OCR},
-    utils::fetch_prices_and_items,
+    diff::{DatabaseDiff, PriceChange},
+    locale::Locale,
+    ocr::{
+        normalize_string, reward_image_to_reward_names, set_ocr_language, Frame, Localiser, OCR,
+    },
+    sets::Inventory,
+    theme::{set_preset_themes, set_theme_definitions, Theme, ThemeDefinitions},
+    theme_fit::{fit_custom_theme, load_samples},
+    theme_presets::{resolve_theme, ThemePreset},
+    utils::{DataCache, DataSource},
+    wfinfo_data::item_data::RelicTier,
 };
 
-fn run_detection(capturer: &Window, db: &Database) {
-    let frame = capturer.capture_image().unwrap();
-    info!("Captured");
-    let image = DynamicImage::ImageRgba8(frame);
-    info!("Converted");
-    let text = reward_image_to_reward_names(image, None);
-    let text = text.iter().map(|s| normalize_string(s));
-    debug!("{:#?}", text);
+fn run_detection(
+    rewards: Vec<MatchedReward>,
+    db: &Database,
+    inventory: Option<&Inventory>,
+    min_volume: usize,
+    need_weight: f32,
+) {
+    debug!("{:#?}", rewards);
 
-    let items: Vec<_> = text.map(|s| db.find_item(&s, None)).collect();
+    let items: Vec<_> = rewards.iter().map(|reward| reward.item).collect();
 
-    let best = items
+    let best = rewards
         .iter()
-        .map(|item| {
+        .map(|MatchedReward { reward, item }| {
             item.map(|item| {
-                item.platinum
-                    .max(item.ducats as f32 / 10.0 + item.platinum / 100.0)
+                // Items that barely sell are only worth their ducats
+                let platinum = if item.is_liquid(min_volume) {
+                    item.platinum
+                } else {
+                    0.0
+                };
+                let value = platinum.max(item.ducats as f32 / 10.0 + item.platinum / 100.0);
+                if db.still_needed(item, &reward.status) == Some(true) {
+                    value * need_weight
+                } else {
+                    value
+                }
             })
             .unwrap_or(0.0)
         })
@@ -48,20 +75,50 @@ fn run_detection(capturer: &Window, db: &Database) {
 
     for (index, item) in items.iter().enumerate() {
         if let Some(item) = item {
+            let volume = item
+                .market
+                .yesterday_volume
+                .map_or_else(|| "?".to_string(), |volume| volume.to_string());
+            let status = &rewards[index].reward.status;
+            let owned = match status.owned {
+                Some(owned) if status.crafted => format!(" ({owned} owned, crafted)"),
+                Some(owned) => format!(" ({owned} owned)"),
+                None if status.crafted => " (crafted)".to_string(),
+                None => String::new(),
+            };
             info!(
-                "{}\n\t{}\t{}\t{}",
+                "{}{}{}\n\t{}\t{}\t{} sold{}\t{}",
                 item.drop_name,
+                if item.vaulted { " (vaulted)" } else { "" },
+                owned,
                 item.platinum,
                 item.ducats as f32 / 10.0,
+                volume,
+                if item.is_liquid(min_volume) {
+                    ""
+                } else {
+                    " (illiquid)"
+                },
                 if Some(index) == best { "<----" } else { "" }
             );
         } else {
             warn!("Unknown item\n\tUnknown");
         }
     }
+
+    if let Some(inventory) = inventory {
+        for (index, set) in db.rewards_completing_sets(inventory, &items) {
+            info!(
+                "{} completes {}",
+                items[index].map_or("", |item| &item.drop_name),
+                set.name
+            );
+        }
+    }
 }
 
-fn log_watcher(path: PathBuf, event_sender: mpsc::Sender<()>) {
+/// Triggers a detection `delay` after the reward screen shows up in the log
+fn log_watcher(path: PathBuf, delay: Duration, event_sender: mpsc::Sender<()>) {
     debug!("Path: {}", path.display());
     let mut position = File::open(&path)
         .unwrap_or_else(|_| panic!("Couldn't open file {}", path.display()))
@@ -105,7 +162,7 @@ fn log_watcher(path: PathBuf, event_sender: mpsc::Sender<()>) {
 
                     if reward_screen_detected {
                         info!("Detected, waiting...");
-                        sleep(Duration::from_millis(1500));
+                        sleep(delay);
                         event_sender.send(()).unwrap();
                     }
 
@@ -121,6 +178,58 @@ fn log_watcher(path: PathBuf, event_sender: mpsc::Sender<()>) {
     });
 }
 
+/// Triggers a detection whenever a screenshot is added to `directory`
+fn directory_watcher(directory: PathBuf, event_sender: mpsc::Sender<()>) {
+    debug!("Watching screenshots in {}", directory.display());
+    thread::spawn(move || {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = watcher(tx, Duration::from_millis(500)).unwrap();
+        watcher
+            .watch(&directory, RecursiveMode::NonRecursive)
+            .unwrap_or_else(|_| panic!("Failed to watch {}", directory.display()));
+
+        loop {
+            match rx.recv() {
+                // Debounced, so the screenshot has been written completely
+                Ok(notify::DebouncedEvent::Create(path)) => {
+                    debug!("New screenshot {}", path.display());
+                    if event_sender.send(()).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    error!("Error: {:?}", err);
+                    return;
+                }
+            }
+        }
+    });
+}
+
+/// Captures a burst of screenshots cropped to the reward area and passes them to `detect` for
+/// every event, until all senders are gone
+fn handle_events(
+    events: &mpsc::Receiver<()>,
+    capture: &mut dyn CaptureSource,
+    burst: &Burst,
+    mut detect: impl FnMut(Vec<Frame>),
+) {
+    while let Ok(()) = events.recv() {
+        info!("Capturing");
+        let start = Instant::now();
+        match capture_burst(capture, burst) {
+            Ok(frames) => {
+                let captured = Instant::now();
+                info!("Captured {} frames in {:?}", frames.len(), captured - start);
+                detect(frames);
+                info!("Processed in {:?}", captured.elapsed());
+            }
+            Err(err) => error!("Failed to capture {}: {err:#}", capture.description()),
+        }
+    }
+}
+
 fn hotkey_watcher(hotkey: HotKey, event_sender: mpsc::Sender<()>) {
     debug!("watching hotkey: {hotkey:?}");
     thread::spawn(move || {
@@ -154,6 +263,8 @@ fn benchmark() -> Result<(), Box<dyn Error>> {
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Arguments {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// Path to the `EE.log` file located in the game installation directory
     ///
     /// Most likely located at `~/.local/share/Steam/steamapps/compatdata/230410/pfx/drive_c/users/steamuser/AppData/Local/Warframe/EE.log`
@@ -163,13 +274,222 @@ struct Arguments {
     /// some systems may require the window name to be specified (e.g. when using gamescope)
     #[arg(short, long, default_value = "Warframe")]
     window_name: String,
+    /// Regular expression matching the title of the Warframe window, takes precedence over
+    /// `--window-name`
+    #[arg(long)]
+    window_regex: Option<String>,
+    /// PID of the Warframe process, takes precedence over `--window-name` and `--window-regex`
+    ///
+    /// Matches the windows whose `_NET_WM_PID` property is set to this PID
+    #[arg(long)]
+    window_pid: Option<u32>,
+    /// Where screenshots are taken from [default: window]
+    ///
+    /// Can also be set using `capture` in the config file
+    #[arg(long, value_enum)]
+    capture: Option<CaptureKind>,
+    /// Directory the screenshots are saved to when using `--capture directory`
+    ///
+    /// Can also be set using `screenshot_directory` in the config file
+    #[arg(long)]
+    screenshot_directory: Option<PathBuf>,
+    /// Index or name of the monitor captured when using `--capture monitor` [default: primary]
+    ///
+    /// Can also be set using `monitor` in the config file
+    #[arg(long)]
+    monitor: Option<String>,
+    /// Part of every capture the game is shown in, as `<width>x<height>+<x>+<y>`
+    ///
+    /// Reward positions are derived from the size of the game, so this has to match the area
+    /// the game is drawn to, e.g. one monitor of a capture spanning several.
+    /// Can also be set using `capture_region` in the config file
+    #[arg(long)]
+    capture_region: Option<CaptureRegion>,
+    /// Number of frames captured while the reward screen fades in [default: 1]
+    ///
+    /// Only applies when capturing a window or monitor, or through the portal.
+    /// Can also be set using `burst.frames` in the config file
+    #[arg(long)]
+    burst_frames: Option<usize>,
+    /// Milliseconds between two frames of a burst [default: 250]
+    ///
+    /// Can also be set using `burst.interval` in the config file
+    #[arg(long)]
+    burst_interval: Option<u64>,
+    /// How the rewards are read from the frames of a burst [default: best]
+    ///
+    /// Can also be set using `burst.selection` in the config file
+    #[arg(long, value_enum)]
+    burst_selection: Option<BurstSelection>,
+    /// Path to a JSON file mapping owned item names to their count
+    ///
+    /// Used to point out rewards that complete a set
+    #[arg(long)]
+    inventory: Option<PathBuf>,
+    /// Minimum number of sales yesterday for an item's platinum value to be considered
+    ///
+    /// Items selling less than this are valued by their ducats only
+    #[arg(long, default_value_t = 1)]
+    min_volume: usize,
+    /// Factor by which the value of rewards still needed for their set is increased
+    ///
+    /// Only applies when `read_status` is enabled in the config file and the reward screen shows
+    /// how many of the item are owned
+    #[arg(long, default_value_t = 1.5)]
+    need_weight: f32,
+    /// Hours after which the downloaded price and item data is refreshed [default: 6]
+    #[arg(long, global = true)]
+    cache_max_age: Option<u64>,
+    /// Base URL to download price and item data from, `file://` URLs refer to a local directory
+    ///
+    /// Can also be set using the `WFINFO_DATA_URL` environment variable or `data_url` in the config file
+    #[arg(long, global = true)]
+    data_url: Option<String>,
+    /// Directory containing `prices.json` and `filtered_items.json` to use instead of downloading
+    ///
+    /// Can also be set using the `WFINFO_MIRROR` environment variable or `mirror` in the config file
+    #[arg(long, global = true)]
+    mirror: Option<PathBuf>,
+    /// Language the game is played in, e.g. `de` [default: en]
+    ///
+    /// Translations are read from `$XDG_CONFIG_HOME/wfinfo/locales/<locale>.json`,
+    /// can also be set using `locale` in the config file
+    #[arg(long)]
+    locale: Option<String>,
+    /// How reward names are found in screenshots [default: heuristic]
+    ///
+    /// Can also be set using `localiser` in the config file
+    #[arg(long, value_enum)]
+    localiser: Option<Localiser>,
+    /// Theme to use instead of detecting it, either a built-in theme or a saved preset
+    ///
+    /// Can also be set using `theme` in the config file
+    #[arg(long)]
+    theme: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Download the latest price and item data
+    Update,
+    /// Work with snapshots of the price and item data
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+    /// Work with the colours used to find the reward names
+    Theme {
+        #[command(subcommand)]
+        command: ThemeCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbCommand {
+    /// Compare two directories containing `prices.json` and `filtered_items.json`
+    Diff {
+        old: PathBuf,
+        new: PathBuf,
+        /// Number of price movers to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum ThemeCommand {
+    /// Fit a custom theme to labelled screenshots and print it as config file JSON
+    ///
+    /// The labels file maps screenshot file names, relative to it, to the expected rewards
+    Fit {
+        labels: PathBuf,
+        /// Also save the fitted theme as a preset with this name
+        #[arg(long)]
+        save: Option<String>,
+    },
+    /// List the built-in themes and saved presets
+    List,
+    /// Print a saved preset as JSON, or write it to a file
+    Export {
+        name: String,
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Save a preset exported as JSON
+    Import {
+        file: PathBuf,
+        /// Save the preset under a different name
+        #[arg(long)]
+        name: Option<String>,
+    },
+}
+
+fn print_diff(diff: &DatabaseDiff, top: usize) {
+    let sections = [
+        ("New items", diff.new_items.clone()),
+        ("Removed items", diff.removed_items.clone()),
+        ("New relics", relic_names(&diff.new_relics)),
+        ("Removed relics", relic_names(&diff.removed_relics)),
+        ("Vaulted relics", relic_names(&diff.vaulted_relics)),
+        ("Unvaulted relics", relic_names(&diff.unvaulted_relics)),
+    ];
+    for (title, names) in sections {
+        if !names.is_empty() {
+            println!("{title}:");
+            names.iter().for_each(|name| println!("\t{name}"));
+        }
+    }
+
+    let print_change = |change: &&PriceChange| {
+        println!(
+            "\t{}:\t{} -> {}\t{:+.1}\t{}",
+            change.name,
+            change.old,
+            change.new,
+            change.absolute(),
+            change
+                .relative()
+                .map_or_else(String::new, |relative| format!("{:+.0}%", relative * 100.0))
+        )
+    };
+    println!("Largest price changes:");
+    diff.movers_by_absolute_change(top)
+        .iter()
+        .for_each(print_change);
+    println!("Largest relative price changes:");
+    diff.movers_by_relative_change(top)
+        .iter()
+        .for_each(print_change);
+}
+
+fn relic_names(relics: &[(RelicTier, String)]) -> Vec<String> {
+    relics
+        .iter()
+        .map(|(tier, name)| format!("{tier:?} {name}"))
+        .collect()
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let arguments = Arguments::parse();
+    let config = Config::load()?;
+    let cache_max_age = arguments
+        .cache_max_age
+        .map_or(config.cache_max_age(), |hours| {
+            Duration::from_secs(hours * 60 * 60)
+        });
+    let cache = DataCache::new(DataCache::default_directory(), cache_max_age);
+    let source = DataSource::resolve(
+        arguments.data_url.as_deref(),
+        arguments.mirror.as_deref(),
+        &config,
+    );
     let default_log_path = PathBuf::from_str(&std::env::var("HOME").unwrap()).unwrap().join(PathBuf::from_str(".local/share/Steam/steamapps/compatdata/230410/pfx/drive_c/users/steamuser/AppData/Local/Warframe/EE.log")?);
     let log_path = arguments.game_log_file_path.unwrap_or(default_log_path);
-    let window_name = arguments.window_name;
+    let window_matcher = match (arguments.window_pid, arguments.window_regex) {
+        (Some(pid), _) => WindowMatcher::Pid(pid),
+        (None, Some(regex)) => WindowMatcher::TitleRegex(Regex::new(&regex)?),
+        (None, None) => WindowMatcher::Title(arguments.window_name),
+    };
     let env = Env::default()
         .filter_or("WFINFO_LOG", "info")
         .write_style_or("WFINFO_STYLE", "always");
@@ -180,32 +500,182 @@ fn main() -> Result<(), Box<dyn Error>> {
         .format_target(false)
         .init();
 
-    let windows = Window::all()?;
-    let Some(warframe_window) = windows.iter().find(|x| x.title() == window_name) else {
-        return Err("Warframe window not found".into());
-    };
+    set_theme_definitions(&ThemeDefinitions::load(&ThemeDefinitions::path())?)?;
+    let locale = Locale::load(
+        arguments
+            .locale
+            .as_deref()
+            .or(config.locale.as_deref())
+            .unwrap_or("en"),
+        &Locale::directory(),
+    )?;
+    set_ocr_language(&locale.tesseract_language)?;
+
+    match arguments.command {
+        Some(Command::Update) => {
+            let (prices, items) = cache.update_prices_and_items(&source)?;
+            info!("Updated {} and {}", prices.display(), items.display());
+            return Ok(());
+        }
+        Some(Command::Db {
+            command: DbCommand::Diff { old, new, top },
+        }) => {
+            let old = Database::load_from_directory(&old)?;
+            let new = Database::load_from_directory(&new)?;
+            print_diff(&DatabaseDiff::new(&old, &new), top);
+            return Ok(());
+        }
+        Some(Command::Theme {
+            command: ThemeCommand::Fit { labels, save },
+        }) => {
+            let samples = load_samples(&labels)?;
+            let (prices, items) = cache.fetch_prices_and_items(&source)?;
+            let db = Database::load_from_file(Some(&prices), Some(&items))
+                .with_rewards(&config.reward_catalogue())
+                .with_locale(&locale);
+            info!("Fitting a theme to {} screenshots", samples.len());
+            let (range, score) =
+                fit_custom_theme(&samples, &db, &config.ocr_options(arguments.localiser));
+            info!("Read {:.0}% of the rewards correctly", score * 100.0);
+            if let Some(name) = save {
+                let path = ThemePreset::new(&name, range.clone()).save(&ThemePreset::directory())?;
+                info!("Saved preset {name} to {}", path.display());
+            }
+            let theme = Theme::Custom(range.to_ordered());
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&serde_json::json!({ "theme": theme }))?
+            );
+            return Ok(());
+        }
+        Some(Command::Theme {
+            command: ThemeCommand::List,
+        }) => {
+            Theme::defined()
+                .iter()
+                .filter_map(Theme::name)
+                .for_each(|name| println!("{name}"));
+            for preset in ThemePreset::load_all(&ThemePreset::directory())? {
+                println!("{}\t{:?}", preset.name, preset.range);
+            }
+            return Ok(());
+        }
+        Some(Command::Theme {
+            command: ThemeCommand::Export { name, output },
+        }) => {
+            let preset = ThemePreset::load(&name, &ThemePreset::directory())?;
+            match output {
+                Some(path) => std::fs::write(path, preset.to_json())?,
+                None => println!("{}", preset.to_json()),
+            }
+            return Ok(());
+        }
+        Some(Command::Theme {
+            command: ThemeCommand::Import { file, name },
+        }) => {
+            let mut preset = ThemePreset::import(&file)?;
+            if let Some(name) = name {
+                preset.name = name;
+            }
+            let path = preset.save(&ThemePreset::directory())?;
+            info!("Saved preset {} to {}", preset.name, path.display());
+            return Ok(());
+        }
+        None => {}
+    }
 
-    debug!(
-        "Capture source resolution: {:?}x{:?}",
-        warframe_window.width(),
-        warframe_window.height()
-    );
+    let capture_kind = arguments.capture.or(config.capture).unwrap_or_default();
+    let screenshot_directory = arguments
+        .screenshot_directory
+        .or(config.screenshot_directory.clone());
+    let mut capture: Box<dyn CaptureSource> = match capture_kind {
+        CaptureKind::Window => {
+            Box::new(WindowCapture::wait(window_matcher, Duration::from_secs(2))?)
+        }
+        CaptureKind::Monitor => Box::new(MonitorCapture::select(
+            arguments.monitor.as_deref().or(config.monitor.as_deref()),
+        )?),
+        CaptureKind::Directory => Box::new(DirectoryCapture::new(
+            screenshot_directory
+                .as_deref()
+                .ok_or("Capturing from a directory requires --screenshot-directory")?,
+        )?),
+        CaptureKind::Portal => Box::new(PortalCapture::new()?),
+        CaptureKind::Stdin => Box::new(ReaderCapture::stdin()),
+    };
+    if let Some(region) = arguments.capture_region.or(config.capture_region) {
+        capture = Box::new(CroppedCapture::new(capture, region));
+    }
+    debug!("Capture source: {}", capture.description());
 
-    let (prices, items) = fetch_prices_and_items()?;
-    let db = Database::load_from_file(Some(&prices), Some(&items));
+    let (prices, items) = cache.fetch_prices_and_items(&source)?;
+    let db = Database::load_from_file(Some(&prices), Some(&items))
+        .with_rewards(&config.reward_catalogue())
+        .with_locale(&locale);
 
     info!("Loaded database");
 
-    let (event_sender, event_receiver) = channel();
+    let inventory: Option<Inventory> = arguments
+        .inventory
+        .map(|path| read_to_string(path).map(|text| serde_json::from_str(&text)))
+        .transpose()?
+        .transpose()?;
+    if let Some(inventory) = inventory.as_ref() {
+        for progress in db.closest_sets(inventory).iter().take(5) {
+            info!(
+                "{}: {}/{} parts",
+                progress.set.name, progress.owned, progress.required
+            );
+        }
+    }
+
+    let options = config.ocr_options(arguments.localiser);
+    let presets = ThemePreset::load_all(&ThemePreset::directory())?;
+    set_preset_themes(presets.iter().map(ThemePreset::theme).collect());
+    let theme = match arguments.theme.as_deref() {
+        Some(name) => Some(resolve_theme(name, &ThemePreset::directory())?),
+        None => config.theme.clone(),
+    };
 
-    log_watcher(log_path, event_sender.clone());
-    hotkey_watcher("F12".parse()?, event_sender);
+    let mut burst = config.burst.clone();
+    burst.frames = arguments.burst_frames.unwrap_or(burst.frames);
+    burst.interval = arguments.burst_interval.unwrap_or(burst.interval);
+    burst.selection = arguments.burst_selection.unwrap_or(burst.selection);
+    let burst_delay = Duration::from_millis(burst.delay);
+    if matches!(capture_kind, CaptureKind::Directory | CaptureKind::Stdin) {
+        // Every screenshot is only used once
+        burst.frames = 1;
+    }
 
-    while let Ok(()) = event_receiver.recv() {
-        info!("Capturing");
-        run_detection(warframe_window, &db);
+    let (event_sender, event_receiver) = channel();
+
+    match capture_kind {
+        // A single image, read it right away and exit afterwards
+        CaptureKind::Stdin => {
+            event_sender.send(())?;
+            drop(event_sender);
+        }
+        CaptureKind::Directory => {
+            log_watcher(log_path, burst_delay, event_sender.clone());
+            directory_watcher(screenshot_directory.unwrap_or_default(), event_sender);
+        }
+        CaptureKind::Window | CaptureKind::Monitor | CaptureKind::Portal => {
+            log_watcher(log_path, burst_delay, event_sender.clone());
+            hotkey_watcher("F12".parse()?, event_sender);
+        }
     }
 
+    handle_events(&event_receiver, capture.as_mut(), &burst, |frames| {
+        let rewards = detect_burst_rewards(frames, theme.as_ref(), &options, burst.selection, &db);
+        run_detection(
+            rewards,
+            &db,
+            inventory.as_ref(),
+            arguments.min_volume,
+            arguments.need_weight,
+        )
+    });
+
     drop(OCR.lock().unwrap().take());
     Ok(())
 }
@@ -216,15 +686,37 @@ mod test {
     use std::fs::read_to_string;
 
     use image::io::Reader;
+    use image::DynamicImage;
     use indexmap::IndexMap;
     use rayon::prelude::*;
     use tesseract::Tesseract;
+    use wfinfo::capture::ScriptedCapture;
     use wfinfo::ocr::detect_theme;
     use wfinfo::ocr::extract_parts;
+    use wfinfo::ocr::reward_image_to_reward_names_with;
+    use wfinfo::ocr::OcrOptions;
+    use wfinfo::preprocess::Preprocessing;
     use wfinfo::testing::Label;
 
     use super::*;
 
+    #[test]
+    fn events_trigger_captures() {
+        let (sender, receiver) = channel();
+        let mut capture = ScriptedCapture::new([DynamicImage::new_rgb8(16, 9)]);
+        sender.send(()).unwrap();
+        sender.send(()).unwrap();
+        drop(sender);
+
+        let mut detected = Vec::new();
+        handle_events(&receiver, &mut capture, &Burst::default(), |frames| {
+            detected.push(frames[0].screen_width)
+        });
+        // The second capture fails as the frames ran out, which is logged instead of panicking
+        assert_eq!(detected, [16]);
+        assert_eq!(capture.captures, 2);
+    }
+
     #[test]
     fn single_image() {
         let image = Reader::open(format!("test-images/{}.png", 1))
@@ -234,7 +726,7 @@ mod test {
         let text = reward_image_to_reward_names(image, None);
         let text = text.iter().map(|s| normalize_string(s));
         println!("{:#?}", text);
-        let db = Database::load_from_file(None, None);
+        let db = Database::from_fixture();
         let items: Vec<_> = text.map(|s| db.find_item(&s, None)).collect();
         println!("{:#?}", items);
 
@@ -270,7 +762,7 @@ mod test {
             let text: Vec<_> = text.iter().map(|s| normalize_string(s)).collect();
             println!("{:#?}", text);
 
-            let db = Database::load_from_file(None, None);
+            let db = Database::from_fixture();
             let items: Vec<_> = text.iter().map(|s| db.find_item(s, None)).collect();
             println!("{:#?}", items);
             println!("{}", filename);
@@ -289,6 +781,46 @@ mod test {
         }
     }
 
+    /// Share of the labelled images whose rewards are all recognised correctly
+    fn wfi_images_success_rate(options: &OcrOptions) -> f32 {
+        let labels: BTreeMap<String, Label> =
+            serde_json::from_str(&read_to_string("WFI test images/labels.json").unwrap()).unwrap();
+        let total = labels.len();
+        let success_count: usize = labels
+            .into_par_iter()
+            .map(|(filename, label)| {
+                let image = Reader::open("WFI test images/".to_string() + &filename)
+                    .unwrap()
+                    .decode()
+                    .unwrap();
+                let text = reward_image_to_reward_names_with(image, None, options);
+                println!("{:#?}", text);
+
+                let db = Database::from_fixture();
+                let items: Vec<_> = text
+                    .iter()
+                    .map(|s| db.find_item_multiline(s, None))
+                    .collect();
+                println!("{:#?}", items);
+                println!("{}", filename);
+
+                let item_names = items
+                    .iter()
+                    .map(|item| item.map(|item| item.drop_name.clone()));
+
+                if item_names.zip(label.items).all(|(result, expectation)| {
+                    expectation == result.unwrap_or_else(|| "".to_string())
+                }) {
+                    1
+                } else {
+                    0
+                }
+            })
+            .sum();
+
+        success_count as f32 / total as f32
+    }
+
     #[test]
     fn wfi_images_99_percent() {
         let labels: BTreeMap<String, Label> =
@@ -305,7 +837,7 @@ mod test {
                 let text: Vec<_> = text.iter().map(|s| normalize_string(s)).collect();
                 println!("{:#?}", text);
 
-                let db = Database::load_from_file(None, None);
+                let db = Database::from_fixture();
                 let items: Vec<_> = text.iter().map(|s| db.find_item(s, None)).collect();
                 println!("{:#?}", items);
                 println!("{}", filename);
@@ -328,6 +860,87 @@ mod test {
         assert!(success_rate > 0.95, "Success rate: {success_rate}");
     }
 
+    #[test]
+    #[ignore = "needs the labelled screenshots in `WFI test images/`"]
+    fn wfi_images_template_localiser() {
+        let heuristic = wfi_images_success_rate(&OcrOptions {
+            localiser: Localiser::Heuristic,
+            ..OcrOptions::default()
+        });
+        let template = wfi_images_success_rate(&OcrOptions {
+            localiser: Localiser::Template,
+            ..OcrOptions::default()
+        });
+        println!("Heuristic: {heuristic}, template: {template}");
+        assert!(template > 0.95, "Success rate: {template}");
+    }
+
+    #[test]
+    #[ignore = "needs the labelled screenshots in `WFI test images/`"]
+    fn wfi_images_split_lines() {
+        let whole = wfi_images_success_rate(&OcrOptions::default());
+        let split = wfi_images_success_rate(&OcrOptions {
+            split_lines: true,
+            ..OcrOptions::default()
+        });
+        println!("Whole names: {whole}, split lines: {split}");
+        assert!(split > 0.95, "Success rate: {split}");
+    }
+
+    #[test]
+    #[ignore = "needs the labelled screenshots in `WFI test images/`"]
+    fn wfi_images_preprocessing_stages() {
+        let stages = [
+            ("none", Preprocessing::default()),
+            (
+                "otsu fallback",
+                Preprocessing {
+                    otsu_fallback: true,
+                    ..Preprocessing::default()
+                },
+            ),
+            (
+                "upscale",
+                Preprocessing {
+                    upscale: 2,
+                    ..Preprocessing::default()
+                },
+            ),
+            (
+                "morphology",
+                Preprocessing {
+                    morphology: true,
+                    ..Preprocessing::default()
+                },
+            ),
+            (
+                "deskew",
+                Preprocessing {
+                    deskew: true,
+                    ..Preprocessing::default()
+                },
+            ),
+            (
+                "padding",
+                Preprocessing {
+                    padding: 10,
+                    ..Preprocessing::default()
+                },
+            ),
+        ];
+        for (name, preprocessing) in stages {
+            let success_rate = wfi_images_success_rate(&OcrOptions {
+                preprocessing,
+                ..OcrOptions::default()
+            });
+            println!("{name}: {success_rate}");
+            assert!(
+                success_rate > 0.95,
+                "Success rate with {name}: {success_rate}"
+            );
+        }
+    }
+
     // #[test]
     #[allow(dead_code)]
     fn images() {
@@ -338,10 +951,11 @@ mod test {
                 .decode()
                 .unwrap();
 
-            let theme = detect_theme(&image);
+            let frame = Frame::new(image);
+            let theme = detect_theme(&frame).unwrap();
             println!("Theme: {:?}", theme);
 
-            let parts = extract_parts(&image, theme);
+            let parts = extract_parts(&frame, theme);
 
             let mut ocr =
                 Tesseract::new(None, Some("eng")).expect("Could not initialize Tesseract");
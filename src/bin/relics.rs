@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use wfinfo::{
     database::Database,
+    utils::fetch_prices_and_items,
     wfinfo_data::item_data::{Refinement, Relic},
 };
 
@@ -71,8 +72,37 @@ fn best_trace_dump(database: &Database) {
         .for_each(|(name, value)| println!("{}:  \t{}", name, value));
 }
 
+fn relics_dropping(database: &Database, needle: &str) {
+    let Some(item) = database.find_item_by_name(needle) else {
+        println!("Unknown item: {needle}");
+        return;
+    };
+    println!("{}", item.name);
+
+    let drops = database.relics_dropping(&item.name);
+    if drops.is_empty() {
+        println!("Not dropped by any relic");
+        return;
+    }
+    for drop in drops {
+        let chances: Vec<_> = Refinement::ALL
+            .iter()
+            .map(|refinement| format!("{:.0}%", drop.chance(*refinement) * 100.0))
+            .collect();
+        println!(
+            "{:?} {}:\t{:?}\t{}{}",
+            drop.tier,
+            drop.relic,
+            drop.rarity,
+            chances.join(" / "),
+            if drop.vaulted { "\tvaulted" } else { "" }
+        );
+    }
+}
+
 fn main() {
-    let database = Database::load_from_file(None, None);
+    let (prices, items) = fetch_prices_and_items().expect("Failed to fetch price and item data");
+    let database = Database::load_from_file(Some(&prices), Some(&items));
     let mut args = std::env::args().skip(1);
     let relics = match args
         .next()
@@ -88,6 +118,11 @@ fn main() {
             best_trace_dump(&database);
             return;
         }
+        "where" => {
+            let needle = args.collect::<Vec<_>>().join(" ");
+            relics_dropping(&database, &needle);
+            return;
+        }
         s => panic!("Invalid relic type: {s}"),
     };
     let relic_count: u32 = args
@@ -1,4 +1,5 @@
 use std::{
+    path::Path,
     sync::mpsc::{
         channel, Receiver, Sender,
         TryRecvError::{Disconnected, Empty},
@@ -11,12 +12,20 @@ use eframe::{
     epaint::ColorImage,
 };
 use egui_extras::RetainedImage;
-use image::{io::Reader, DynamicImage, Rgb};
-use palette::{FromColor, Hsl, Srgb};
+use image::{io::Reader, DynamicImage, Rgb, RgbImage};
+use levenshtein::levenshtein;
 use wfinfo::{
+    config::Config,
     database::Database,
-    ocr::{self, normalize_string},
+    locale::Locale,
+    ocr::{
+        normalize_string, reward_search_area, set_ocr_language, threshold_image, trace_rewards,
+        Frame, OcrOptions, Rect,
+    },
     theme::{HslRange, Theme},
+    theme_fit::load_samples,
+    theme_presets::ThemePreset,
+    utils::fetch_prices_and_items,
 };
 
 fn main() {
@@ -28,147 +37,471 @@ fn main() {
     );
 }
 
+/// A screenshot passed on the command line, labelled if it was listed in a `labels.json`
+struct LoadedImage {
+    name: String,
+    frame: Frame,
+    items: Option<Vec<String>>,
+}
+
+/// Loads every argument, either a screenshot or a `labels.json` listing labelled screenshots
+fn load_images() -> Vec<LoadedImage> {
+    std::env::args()
+        .skip(1)
+        .flat_map(|argument| {
+            let path = Path::new(&argument);
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                let directory = path.parent().unwrap_or(Path::new(""));
+                load_samples(path)
+                    .unwrap()
+                    .into_iter()
+                    .map(|sample| LoadedImage {
+                        name: directory.join(sample.name).display().to_string(),
+                        frame: Frame::new(sample.image),
+                        items: Some(sample.items),
+                    })
+                    .collect()
+            } else {
+                vec![LoadedImage {
+                    name: argument.clone(),
+                    frame: Frame::new(Reader::open(&argument).unwrap().decode().unwrap()),
+                    items: None,
+                }]
+            }
+        })
+        .collect()
+}
+
+/// What was read from one reward slot
+struct SlotResult {
+    text: String,
+    /// Matched item along with the edit distance of its name to the text
+    item: Option<(String, usize)>,
+    expected: Option<String>,
+}
+
+impl SlotResult {
+    fn is_correct(&self) -> bool {
+        self.expected.is_some()
+            && self.item.as_ref().map(|(name, _)| name) == self.expected.as_ref()
+    }
+}
+
+/// Every pipeline stage of one screenshot for the current settings
+struct Inspection {
+    /// UI scale chosen by the heuristic localiser
+    scale: Option<f32>,
+    /// Thresholded part box with the slot boxes drawn in
+    crop: DynamicImage,
+    /// Reward name images as passed to OCR
+    slot_images: Vec<DynamicImage>,
+    slots: Vec<SlotResult>,
+}
+
+struct InspectionView {
+    scale: Option<f32>,
+    crop: RetainedImage,
+    slot_images: Vec<RetainedImage>,
+    slots: Vec<SlotResult>,
+}
+
+/// Settings to inspect all images with, along with a generation to tell stale results apart
+type Request = (usize, usize, HslRange<f32>);
+/// Generation of the request, image index and result
+type Response = (usize, usize, Inspection);
+
 struct MyApp {
-    original_images: Vec<DynamicImage>,
+    images: Vec<LoadedImage>,
     selected_image_index: usize,
-    image: Option<RetainedImage>,
+    prefilter: Option<RetainedImage>,
 
-    ocr_request_sender: Sender<(usize, HslRange<f32>)>,
-    ocr_response_receiver: Receiver<Vec<(String, String)>>,
-    ocr_result: Option<Vec<(String, String)>>,
+    request_sender: Sender<Request>,
+    response_receiver: Receiver<Response>,
+    generation: usize,
+    inspections: Vec<Option<InspectionView>>,
 
     settings: HslRange<f32>,
+    preset_name: String,
+    preset_status: String,
 }
 
 impl Default for MyApp {
     fn default() -> Self {
-        let original_images = std::env::args()
-            .skip(1)
-            .map(|name| Reader::open(name).unwrap().decode().unwrap())
-            .collect();
+        let images = load_images();
         let settings = HslRange {
             saturation: 0.50..1.0,
             lightness: 0.15..1.0,
             hue: -10.0..10.0,
         };
-        let (ocr_request_sender, ocr_response_receiver) = spawn_ocr_thread(&original_images);
-        Self {
-            original_images,
+        let (request_sender, response_receiver) = spawn_ocr_thread(&images);
+        let mut app = Self {
+            inspections: images.iter().map(|_| None).collect(),
+            images,
             selected_image_index: 0,
-            image: None,
+            prefilter: None,
 
-            ocr_request_sender,
-            ocr_response_receiver,
-            ocr_result: None,
+            request_sender,
+            response_receiver,
+            generation: 0,
 
             settings,
-        }
+            preset_name: String::new(),
+            preset_status: String::new(),
+        };
+        app.settings_changed();
+        app
     }
 }
 
-#[allow(clippy::type_complexity)]
-fn spawn_ocr_thread(
-    images: &Vec<DynamicImage>,
-) -> (
-    Sender<(usize, HslRange<f32>)>,
-    Receiver<Vec<(String, String)>>,
-) {
-    let (request_sender, request_receiver): (Sender<_>, Receiver<_>) = channel();
+fn spawn_ocr_thread(images: &[LoadedImage]) -> (Sender<Request>, Receiver<Response>) {
+    let (request_sender, request_receiver): (Sender<Request>, Receiver<Request>) = channel();
     let (response_sender, response_receiver) = channel();
-    let images = images.to_owned();
+    let images: Vec<_> = images
+        .iter()
+        .map(|image| (image.frame.clone(), image.items.clone()))
+        .collect();
 
     thread::spawn(move || {
-        let database = Database::load_from_file(None, None);
-        loop {
-            let (mut index, mut last_request): (usize, HslRange<f32>) =
-                request_receiver.recv().unwrap();
-            loop {
-                match request_receiver.try_recv() {
-                    Ok(request) => (index, last_request) = request,
-                    Err(Empty) => break,
-                    Err(Disconnected) => return,
+        // Inspect the rewards the way wfinfo reads them with the same config
+        let config = Config::load().expect("Failed to load the config");
+        let locale = Locale::load(
+            config.locale.as_deref().unwrap_or("en"),
+            &Locale::directory(),
+        )
+        .expect("Failed to load the locale");
+        set_ocr_language(&locale.tesseract_language).unwrap();
+        let options = config.ocr_options(None);
+        let (prices, items) =
+            fetch_prices_and_items().expect("Failed to fetch price and item data");
+        let database = Database::load_from_file(Some(&prices), Some(&items))
+            .with_rewards(&config.reward_catalogue())
+            .with_locale(&locale);
+        let mut request = request_receiver.recv().ok();
+        while let Some((generation, first, settings)) = request.take() {
+            // The selected image first, then all others to compute the accuracy
+            let order = (first..images.len()).chain(0..first);
+            for index in order {
+                let (frame, items) = &images[index];
+                let inspection = inspect(&database, frame, items.as_deref(), &settings, &options);
+                if response_sender
+                    .send((generation, index, inspection))
+                    .is_err()
+                {
+                    return;
+                }
+                // Restart with the latest settings as soon as they change
+                loop {
+                    match request_receiver.try_recv() {
+                        Ok(newer) => request = Some(newer),
+                        Err(Empty) => break,
+                        Err(Disconnected) => return,
+                    }
+                }
+                if request.is_some() {
+                    break;
                 }
             }
-            let image = &images[index];
-            let strings = ocr::reward_image_to_reward_names(
-                image.clone(),
-                Some(Theme::Custom(last_request.to_ordered())),
-            );
-            let results = strings
-                .iter()
-                .map(|string| {
-                    let item = database.find_item(&normalize_string(string), None);
-                    (
-                        string.to_owned(),
-                        item.map(|item| item.drop_name.to_owned())
-                            .unwrap_or_else(|| "None".to_string()),
-                    )
-                })
-                .collect();
-            response_sender.send(results).unwrap();
+            if request.is_none() {
+                request = request_receiver.recv().ok();
+            }
         }
     });
 
     (request_sender, response_receiver)
 }
 
+fn inspect(
+    database: &Database,
+    frame: &Frame,
+    items: Option<&[String]>,
+    settings: &HslRange<f32>,
+    options: &OcrOptions,
+) -> Inspection {
+    let theme = Theme::Custom(settings.to_ordered());
+    let trace = trace_rewards(frame, &theme, options);
+
+    let image = &frame.image;
+    let crop_area = trace.part_box.map_or_else(
+        || bounding_box(&trace.slots).unwrap_or_else(|| reward_search_area(frame)),
+        |fit| fit.part_box,
+    );
+    let mut crop = threshold_image(
+        &image.crop_imm(crop_area.x, crop_area.y, crop_area.width, crop_area.height),
+        &theme,
+    )
+    .to_rgb8();
+    for slot in trace.slots.iter() {
+        draw_rect(
+            &mut crop,
+            slot.x.saturating_sub(crop_area.x),
+            slot.y.saturating_sub(crop_area.y),
+            slot.width,
+            slot.height,
+        );
+    }
+
+    let slot_images = trace
+        .slots
+        .iter()
+        .map(|slot| {
+            options.preprocessing.apply(
+                &image.crop_imm(slot.x, slot.y, slot.width, slot.height),
+                &theme,
+            )
+        })
+        .collect();
+    let slots = trace
+        .rewards
+        .iter()
+        .enumerate()
+        .map(|(index, reward)| SlotResult {
+            text: reward.name.trim().to_owned(),
+            item: database
+                .find_item_multiline(&reward.name, None)
+                .map(|item| {
+                    let distance = levenshtein(
+                        &normalize_string(&item.drop_name),
+                        &normalize_string(&reward.name),
+                    );
+                    (item.drop_name.clone(), distance)
+                }),
+            expected: items.and_then(|items| items.get(index).cloned()),
+        })
+        .collect();
+
+    Inspection {
+        scale: trace.part_box.map(|fit| fit.scale),
+        crop: DynamicImage::ImageRgb8(crop),
+        slot_images,
+        slots,
+    }
+}
+
+fn bounding_box(rects: &[Rect]) -> Option<Rect> {
+    let left = rects.iter().map(|rect| rect.x).min()?;
+    let top = rects.iter().map(|rect| rect.y).min()?;
+    let right = rects.iter().map(|rect| rect.x + rect.width).max()?;
+    let bottom = rects.iter().map(|rect| rect.y + rect.height).max()?;
+    Some(Rect {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    })
+}
+
+/// Draws the outline of a rectangle in red, clipped to the image
+fn draw_rect(image: &mut RgbImage, x: u32, y: u32, width: u32, height: u32) {
+    let (image_width, image_height) = image.dimensions();
+    let right = (x + width).min(image_width).saturating_sub(1);
+    let bottom = (y + height).min(image_height).saturating_sub(1);
+    for px in x.min(right)..=right {
+        image.put_pixel(px, y.min(bottom), Rgb([255, 0, 0]));
+        image.put_pixel(px, bottom, Rgb([255, 0, 0]));
+    }
+    for py in y.min(bottom)..=bottom {
+        image.put_pixel(x.min(right), py, Rgb([255, 0, 0]));
+        image.put_pixel(right, py, Rgb([255, 0, 0]));
+    }
+}
+
+impl MyApp {
+    /// Shows the new settings on the thresholded prefilter right away and queues the OCR
+    fn settings_changed(&mut self) {
+        if self.images.is_empty() {
+            return;
+        }
+        let frame = &self.images[self.selected_image_index].frame;
+        let area = reward_search_area(frame);
+        let prefilter = threshold_image(
+            &frame
+                .image
+                .crop_imm(area.x, area.y, area.width, area.height),
+            &Theme::Custom(self.settings.to_ordered()),
+        );
+        self.prefilter = Some(convert_image(&prefilter));
+
+        self.generation += 1;
+        self.inspections.iter_mut().for_each(|view| *view = None);
+        self.request_sender
+            .send((
+                self.generation,
+                self.selected_image_index,
+                self.settings.clone(),
+            ))
+            .unwrap();
+    }
+
+    fn select_image(&mut self, index: usize) {
+        self.selected_image_index = index;
+        self.settings_changed();
+    }
+
+    /// Correct and labelled rewards over all inspected images, along with the inspected images
+    fn accuracy(&self) -> (usize, usize, usize) {
+        let inspected: Vec<_> = self.inspections.iter().flatten().collect();
+        let correct = inspected
+            .iter()
+            .flat_map(|view| view.slots.iter())
+            .filter(|slot| slot.is_correct())
+            .count();
+        let labelled = self
+            .images
+            .iter()
+            .zip(self.inspections.iter())
+            .filter(|(_, view)| view.is_some())
+            .filter_map(|(image, _)| image.items.as_ref())
+            .map(|items| items.len())
+            .sum();
+        (correct, labelled, inspected.len())
+    }
+}
+
 impl eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         ctx.request_repaint();
+        if self.images.is_empty() {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.label("Pass screenshots or a labels.json file as arguments");
+            });
+            return;
+        }
+        let count = self.images.len();
         if ctx.input_mut().consume_key(egui::Modifiers::NONE, Key::N) {
-            self.selected_image_index =
-                (self.selected_image_index + 1) % self.original_images.len();
-            self.image = None;
-            self.ocr_request_sender
-                .send((self.selected_image_index, self.settings.clone()))
-                .unwrap();
-            self.ocr_result = None;
+            self.select_image((self.selected_image_index + 1) % count);
         }
         if ctx.input_mut().consume_key(egui::Modifiers::NONE, Key::P) {
-            self.selected_image_index =
-                (self.selected_image_index - 1) % self.original_images.len();
-            self.image = None;
-            self.ocr_request_sender
-                .send((self.selected_image_index, self.settings.clone()))
-                .unwrap();
-            self.ocr_result = None;
-        }
-        if self.image.is_none() {
-            let image = self.process_image(&self.original_images[self.selected_image_index]);
-            self.image = Some(convert_image(&image));
-            self.ocr_request_sender
-                .send((self.selected_image_index, self.settings.clone()))
-                .unwrap();
+            self.select_image((self.selected_image_index + count - 1) % count);
         }
 
-        match self.ocr_response_receiver.try_recv() {
-            Ok(response) => self.ocr_result = Some(response),
-            Err(Empty) => {}
-            other => {
-                other.unwrap();
+        loop {
+            match self.response_receiver.try_recv() {
+                Ok((generation, index, inspection)) => {
+                    if generation == self.generation {
+                        self.inspections[index] = Some(InspectionView {
+                            scale: inspection.scale,
+                            crop: convert_image(&inspection.crop),
+                            slot_images: inspection.slot_images.iter().map(convert_image).collect(),
+                            slots: inspection.slots,
+                        });
+                    }
+                }
+                Err(Empty) => break,
+                other => {
+                    other.unwrap();
+                }
             }
         }
 
-        egui::CentralPanel::default().show(ctx, |ui| match self.image.as_ref() {
-            Some(image) => {
-                image.show_scaled(ui, 3.0);
-                if let Some(detections) = self.ocr_result.as_ref() {
-                    ui.label(format!("{:#?}", detections));
+        egui::TopBottomPanel::top("Top Panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{}/{}: {} (N/P to switch)",
+                    self.selected_image_index + 1,
+                    count,
+                    self.images[self.selected_image_index].name
+                ));
+                let (correct, labelled, inspected) = self.accuracy();
+                let accuracy = if labelled == 0 {
+                    "no labels".to_string()
                 } else {
+                    format!(
+                        "{correct}/{labelled} rewards ({:.0}%)",
+                        correct as f32 / labelled as f32 * 100.0
+                    )
+                };
+                ui.label(format!(
+                    "Accuracy over {inspected}/{count} images: {accuracy}"
+                ));
+                if inspected < count {
                     ui.spinner();
                 }
-            }
-            None => {
-                ui.spinner();
-            }
+            });
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::both().show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.vertical(|ui| {
+                        ui.label("Prefilter");
+                        if let Some(prefilter) = self.prefilter.as_ref() {
+                            prefilter.show_scaled(ui, 1.0);
+                        }
+                    });
+                    match self.inspections[self.selected_image_index].as_ref() {
+                        Some(view) => {
+                            ui.vertical(|ui| {
+                                ui.label(match view.scale {
+                                    Some(scale) => format!("Final crop at scale {scale:.2}"),
+                                    None => "Final crop of the template boxes".to_string(),
+                                });
+                                view.crop.show_scaled(ui, 1.0);
+                            });
+                        }
+                        None => {
+                            ui.spinner();
+                        }
+                    }
+                });
+                if let Some(view) = self.inspections[self.selected_image_index].as_ref() {
+                    ui.horizontal(|ui| {
+                        for (image, slot) in view.slot_images.iter().zip(view.slots.iter()) {
+                            ui.vertical(|ui| {
+                                image.show_scaled(ui, 1.0);
+                                ui.label(format!("OCR: {:?}", slot.text));
+                                ui.label(match slot.item.as_ref() {
+                                    Some((name, distance)) => {
+                                        format!("Item: {name} (distance {distance})")
+                                    }
+                                    None => "Item: None".to_string(),
+                                });
+                                if let Some(expected) = slot.expected.as_ref() {
+                                    let mark = if slot.is_correct() { "✔" } else { "✘" };
+                                    ui.label(format!("{mark} expected {expected}"));
+                                }
+                            });
+                        }
+                    });
+                }
+            });
         });
         egui::TopBottomPanel::bottom("Bottom Panel").show(ctx, |ui| {
-            if ui
-                .add(
-                    egui::Slider::new(&mut self.settings.saturation.start, 0.0..=1.0)
-                        .text("Saturation min"),
-                )
-                .changed()
+            let mut loaded = false;
+            ui.horizontal(|ui| {
+                ui.label("Preset");
+                ui.text_edit_singleline(&mut self.preset_name);
+                if ui.button("Save").clicked() {
+                    self.preset_status =
+                        match ThemePreset::new(&self.preset_name, self.settings.clone())
+                            .save(&ThemePreset::directory())
+                        {
+                            Ok(path) => format!("Saved to {}", path.display()),
+                            Err(err) => format!("{err:#}"),
+                        };
+                }
+                if ui.button("Load").clicked() {
+                    self.preset_status =
+                        match ThemePreset::load(&self.preset_name, &ThemePreset::directory()) {
+                            Ok(preset) => {
+                                self.settings = preset.range;
+                                loaded = true;
+                                "Loaded".to_string()
+                            }
+                            Err(err) => format!("{err:#}"),
+                        };
+                }
+                ui.label(&self.preset_status);
+            });
+            if loaded
+                || ui
+                    .add(
+                        egui::Slider::new(&mut self.settings.saturation.start, 0.0..=1.0)
+                            .text("Saturation min"),
+                    )
+                    .changed()
                 || ui
                     .add(
                         egui::Slider::new(&mut self.settings.saturation.end, 0.0..=1.0)
@@ -200,69 +533,12 @@ impl eframe::App for MyApp {
                     )
                     .changed()
             {
-                self.image = None;
-                self.ocr_request_sender
-                    .send((self.selected_image_index, self.settings.clone()))
-                    .unwrap();
-                self.ocr_result = None;
+                self.settings_changed();
             };
         });
     }
 }
 
-impl MyApp {
-    fn process_image(&self, image: &DynamicImage) -> DynamicImage {
-        const PIXEL_REWARD_WIDTH: f32 = 968.0;
-        const PIXEL_REWARD_HEIGHT: f32 = 235.0;
-        const PIXEL_REWARD_YDISPLAY: f32 = 316.0;
-        const PIXEL_REWARD_LINE_HEIGHT: f32 = 48.0;
-
-        let screen_scaling = if image.width() * 9 > image.height() * 16 {
-            image.height() as f32 / 1080.0
-        } else {
-            image.width() as f32 / 1920.0
-        };
-
-        let width = image.width() as f32;
-        let height = image.height() as f32;
-        let most_width = PIXEL_REWARD_WIDTH * screen_scaling;
-        let most_left = width / 2.0 - most_width / 2.0;
-        // Most Top = pixleRewardYDisplay - pixleRewardHeight + pixelRewardLineHeight
-        //                   (316          -        235        +       44)    *    1.1    =    137
-        let most_top = height / 2.0
-            - ((PIXEL_REWARD_YDISPLAY - PIXEL_REWARD_HEIGHT + PIXEL_REWARD_LINE_HEIGHT)
-                * screen_scaling);
-        let most_bot =
-            height / 2.0 - ((PIXEL_REWARD_YDISPLAY - PIXEL_REWARD_HEIGHT) * screen_scaling * 0.5);
-
-        let mut new_image = image
-            .crop_imm(
-                most_left as u32,
-                most_top as u32,
-                most_width as u32,
-                (most_bot - most_top) as u32,
-            )
-            .to_rgb8();
-
-        for pixel in new_image.pixels_mut() {
-            let rgb = Srgb::from_components((
-                pixel.0[0] as f32 / 255.0,
-                pixel.0[1] as f32 / 255.0,
-                pixel.0[2] as f32 / 255.0,
-            ));
-            let test = Hsl::from_color(rgb);
-
-            let is_theme = self.settings.saturation.contains(&test.saturation)
-                && self.settings.lightness.contains(&test.lightness)
-                && self.settings.hue.contains(&test.hue.to_degrees());
-
-            *pixel = if is_theme { Rgb([0; 3]) } else { Rgb([255; 3]) }
-        }
-
-        DynamicImage::ImageRgb8(new_image)
-    }
-}
-
 fn convert_image(original_image: &DynamicImage) -> RetainedImage {
     let ui_image = ColorImage::from_rgba_unmultiplied(
         [original_image.width() as _, original_image.height() as _],
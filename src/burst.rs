@@ -0,0 +1,305 @@
+use std::{collections::HashMap, thread::sleep, time::Duration};
+
+use clap::ValueEnum;
+use image::{GenericImageView, Pixel};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    capture::CaptureSource,
+    database::{Database, Item},
+    ocr::{
+        detect_rewards, detect_theme, normalize_string, reward_search_area, DetectedReward, Frame,
+        OcrOptions,
+    },
+    theme::Theme,
+};
+
+/// How the rewards are read from the frames of a burst
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BurstSelection {
+    /// Reads the frame showing the most theme coloured text, see [`theme_density`]
+    #[default]
+    Best,
+    /// Reads every frame and picks the item read most often for each slot
+    Vote,
+}
+
+/// Captures several frames while the reward screen fades in, instead of a single one
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Burst {
+    /// Number of frames captured per detection
+    pub frames: usize,
+    /// Milliseconds between two frames
+    pub interval: u64,
+    /// Milliseconds to wait after the reward screen appeared in the log before the first frame
+    pub delay: u64,
+    pub selection: BurstSelection,
+}
+
+impl Default for Burst {
+    fn default() -> Self {
+        Burst {
+            frames: 1,
+            interval: 250,
+            delay: 1500,
+            selection: BurstSelection::default(),
+        }
+    }
+}
+
+/// Captures the frames of a burst, cropped to the reward area, frames failing to capture are skipped
+pub fn capture_burst(
+    capture: &mut dyn CaptureSource,
+    burst: &Burst,
+) -> Result<Vec<Frame>, anyhow::Error> {
+    let mut frames = Vec::new();
+    let mut last_error = None;
+    for index in 0..burst.frames.max(1) {
+        if index > 0 {
+            sleep(Duration::from_millis(burst.interval));
+        }
+        match capture.capture() {
+            Ok(image) => frames.push(Frame::reward_area(&image)),
+            Err(err) => {
+                warn!("Failed to capture frame {index}: {err:#}");
+                last_error = Some(err);
+            }
+        }
+    }
+    match last_error {
+        Some(err) if frames.is_empty() => Err(err),
+        _ => Ok(frames),
+    }
+}
+
+/// Share of the pixels searched for reward names that have the theme's text colour.
+///
+/// Reward names that are still fading in are darker than the theme colours, so the frame
+/// scoring highest shows them most clearly.
+pub fn theme_density(frame: &Frame, theme: &Theme) -> f32 {
+    let area = reward_search_area(frame);
+    let region = frame.image.view(area.x, area.y, area.width, area.height);
+    let total = area.width as usize * area.height as usize;
+    if total == 0 {
+        return 0.0;
+    }
+    let filter = theme.resolved();
+    let matching = region
+        .pixels()
+        .filter(|(_, _, pixel)| filter.matches(pixel.to_rgb()))
+        .count();
+    matching as f32 / total as f32
+}
+
+/// Picks the frame with the highest [`theme_density`].
+///
+/// Without a given theme, it is detected from the last frame, which is the most faded in.
+/// If no theme is found there, the last frame is picked.
+pub fn best_frame(mut frames: Vec<Frame>, theme: Option<&Theme>) -> Option<Frame> {
+    if frames.len() <= 1 {
+        return frames.pop();
+    }
+    let theme = match theme {
+        Some(theme) => theme.clone(),
+        None => match detect_theme(frames.last()?) {
+            Some(theme) => theme,
+            None => return frames.pop(),
+        },
+    };
+    let scores: Vec<_> = frames
+        .iter()
+        .map(|frame| theme_density(frame, &theme))
+        .collect();
+    debug!("Frame scores for {theme:?}: {scores:?}");
+    // Later frames win ties, as they are more likely to be faded in completely
+    let best = scores
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(index, _)| index)?;
+    frames.into_iter().nth(best)
+}
+
+/// A reward read from the reward screen along with the item it matches
+#[derive(Clone, Debug)]
+pub struct MatchedReward<'a> {
+    pub reward: DetectedReward,
+    pub item: Option<&'a Item>,
+}
+
+impl<'a> MatchedReward<'a> {
+    pub fn new(reward: DetectedReward, db: &'a Database) -> MatchedReward<'a> {
+        MatchedReward {
+            item: db.find_item_multiline(&reward.name, None),
+            reward,
+        }
+    }
+}
+
+/// What a reward read votes for
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Vote<'a> {
+    /// Name of the matched item
+    Item(&'a str),
+    /// Normalised text of a read matching no item
+    Text(String),
+}
+
+/// Picks the item read most often for each slot, later frames win ties.
+///
+/// Reads that match no item vote for their text instead.
+pub fn vote_rewards<'a>(reads: &[Vec<DetectedReward>], db: &'a Database) -> Vec<MatchedReward<'a>> {
+    let slots = reads.iter().map(Vec::len).max().unwrap_or(0);
+    (0..slots)
+        .filter_map(|slot| {
+            let candidates: Vec<_> = reads
+                .iter()
+                .filter_map(|read| read.get(slot))
+                .map(|candidate| {
+                    let matched = MatchedReward::new(candidate.clone(), db);
+                    let vote = match matched.item {
+                        Some(item) => Some(Vote::Item(&item.name)),
+                        None => Some(normalize_string(&candidate.name))
+                            .filter(|text| !text.is_empty())
+                            .map(Vote::Text),
+                    };
+                    (matched, vote)
+                })
+                .collect();
+            let mut votes = HashMap::new();
+            for vote in candidates.iter().filter_map(|(_, vote)| vote.clone()) {
+                *votes.entry(vote).or_insert(0) += 1;
+            }
+            candidates
+                .into_iter()
+                .enumerate()
+                .max_by_key(|(index, (_, vote))| {
+                    let count = vote.as_ref().and_then(|vote| votes.get(vote));
+                    (count.copied().unwrap_or(0), *index)
+                })
+                .map(|(_, (matched, _))| matched)
+        })
+        .collect()
+}
+
+/// Reads the rewards from the frames of a burst according to its selection
+/// and matches them to the items of `db`
+pub fn detect_burst_rewards<'a>(
+    frames: Vec<Frame>,
+    theme: Option<&Theme>,
+    options: &OcrOptions,
+    selection: BurstSelection,
+    db: &'a Database,
+) -> Vec<MatchedReward<'a>> {
+    match selection {
+        BurstSelection::Vote if frames.len() > 1 => {
+            let reads: Vec<_> = frames
+                .into_iter()
+                .map(|frame| detect_rewards(frame, theme.cloned(), options))
+                .collect();
+            debug!("Rewards read from each frame: {reads:#?}");
+            vote_rewards(&reads, db)
+        }
+        _ => match best_frame(frames, theme) {
+            Some(frame) => detect_rewards(frame, theme.cloned(), options)
+                .into_iter()
+                .map(|reward| MatchedReward::new(reward, db))
+                .collect(),
+            None => Vec::new(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{DynamicImage, Rgb, RgbImage};
+
+    use crate::{capture::ScriptedCapture, ocr::SlotStatus};
+
+    use super::*;
+
+    /// A 1920x1080 frame with the given share of the reward name area in Vitruvian's text colour
+    fn faded_frame(share: f32) -> Frame {
+        let mut image = RgbImage::new(1920, 1080);
+        let area = reward_search_area(&Frame::new(DynamicImage::ImageRgb8(image.clone())));
+        let text_width = (area.width as f32 * share) as u32;
+        for y in area.y..area.y + area.height {
+            for x in area.x..area.x + text_width {
+                image.put_pixel(x, y, Rgb([190, 169, 102]));
+            }
+        }
+        Frame::reward_area(&DynamicImage::ImageRgb8(image))
+    }
+
+    fn reward(name: &str) -> DetectedReward {
+        DetectedReward {
+            name: name.to_string(),
+            status: SlotStatus::default(),
+        }
+    }
+
+    #[test]
+    fn can_capture_bursts() {
+        let frames = (0..2).map(|_| DynamicImage::new_rgb8(4, 3));
+        let burst = Burst {
+            frames: 3,
+            interval: 0,
+            ..Burst::default()
+        };
+        let mut capture = ScriptedCapture::new(frames);
+        // The third frame fails to capture and is skipped
+        assert_eq!(capture_burst(&mut capture, &burst).unwrap().len(), 2);
+        assert!(capture_burst(&mut capture, &burst).is_err());
+    }
+
+    #[test]
+    fn picks_the_most_faded_in_frame() {
+        let theme = Theme::Vitruvian;
+        assert!(
+            theme_density(&faded_frame(0.5), &theme) > theme_density(&faded_frame(0.1), &theme)
+        );
+
+        let frames = vec![faded_frame(0.1), faded_frame(0.5), faded_frame(0.3)];
+        let best = best_frame(frames, Some(&theme)).unwrap();
+        assert_eq!(
+            theme_density(&best, &theme),
+            theme_density(&faded_frame(0.5), &theme)
+        );
+    }
+
+    #[test]
+    fn can_vote_per_slot() {
+        let db = Database::builder()
+            .add_item(Item::new("Forma Blueprint", 11.0, 0))
+            .add_item(Item::new("Nikana Prime Hilt", 8.0, 45))
+            .build();
+        let reads = [
+            vec![reward("Forma Blueprnt"), reward(""), reward("Kuva")],
+            vec![
+                reward("Forma Blueprint"),
+                reward("Nikana Prime Hilt"),
+                reward("Kuva"),
+            ],
+            vec![
+                reward("Forma Blueprint"),
+                reward("Nikana Prime Hi1t"),
+                reward("Kuvo"),
+            ],
+        ];
+        // Misreadings of an item vote for it, unknown names for their text
+        let votes = vote_rewards(&reads, &db);
+        let items: Vec<_> = votes
+            .iter()
+            .map(|vote| vote.item.map(|item| item.name.as_str()))
+            .collect();
+        assert_eq!(
+            items,
+            [Some("Forma Blueprint"), Some("Nikana Prime Hilt"), None]
+        );
+        assert_eq!(votes[1].reward, reward("Nikana Prime Hi1t"));
+        assert_eq!(votes[2].reward, reward("Kuva"));
+    }
+}
@@ -0,0 +1,672 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fs::{read_dir, remove_file},
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread::sleep,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context};
+use ashpd::desktop::screenshot::Screenshot;
+use clap::ValueEnum;
+use image::{io::Reader, DynamicImage, RgbaImage};
+use log::{debug, info, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use xcap::{Monitor, Window};
+use xcb::x;
+
+/// Where screenshots of the reward screen come from
+pub trait CaptureSource {
+    fn capture(&mut self) -> Result<DynamicImage, anyhow::Error>;
+    /// What is captured, for log messages
+    fn description(&self) -> String;
+}
+
+impl<S: CaptureSource + ?Sized> CaptureSource for Box<S> {
+    fn capture(&mut self) -> Result<DynamicImage, anyhow::Error> {
+        (**self).capture()
+    }
+
+    fn description(&self) -> String {
+        (**self).description()
+    }
+}
+
+/// The capture sources selectable from the command line
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureKind {
+    /// The game window
+    #[default]
+    Window,
+    /// A whole monitor, the primary one unless selected, for when the game window can't be captured
+    Monitor,
+    /// Screenshots requested from the xdg-desktop-portal, for Wayland compositors where the
+    /// window or monitor can't be captured directly
+    Portal,
+    /// The newest screenshot saved to a directory, e.g. by the compositor's screenshot tool
+    Directory,
+    /// A single PNG read from stdin, detects the rewards once and exits
+    Stdin,
+}
+
+/// How the game window is recognised
+#[derive(Clone, Debug)]
+pub enum WindowMatcher {
+    Title(String),
+    TitleRegex(Regex),
+    /// Windows of the process with this PID, as reported by their `_NET_WM_PID` property
+    Pid(u32),
+}
+
+impl WindowMatcher {
+    /// `pid` is the window's process, `None` if it isn't known
+    pub fn matches(&self, title: &str, pid: Option<u32>) -> bool {
+        match self {
+            WindowMatcher::Title(expected) => title == expected,
+            WindowMatcher::TitleRegex(regex) => regex.is_match(title),
+            WindowMatcher::Pid(expected) => pid == Some(*expected),
+        }
+    }
+}
+
+impl std::fmt::Display for WindowMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WindowMatcher::Title(title) => write!(f, "window {title:?}"),
+            WindowMatcher::TitleRegex(regex) => write!(f, "window matching {:?}", regex.as_str()),
+            WindowMatcher::Pid(pid) => write!(f, "window of process {pid}"),
+        }
+    }
+}
+
+/// Reads the `_NET_WM_PID` property X11 windows set to the PID of their process
+struct WindowPids {
+    connection: xcb::Connection,
+    property: x::Atom,
+}
+
+impl WindowPids {
+    fn connect() -> Result<WindowPids, anyhow::Error> {
+        let (connection, _) = xcb::Connection::connect(None)?;
+        let property = connection
+            .wait_for_reply(connection.send_request(&x::InternAtom {
+                only_if_exists: true,
+                name: b"_NET_WM_PID",
+            }))?
+            .atom();
+        Ok(WindowPids {
+            connection,
+            property,
+        })
+    }
+
+    fn get(&self, window_id: u32) -> Option<u32> {
+        if self.property == x::ATOM_NONE {
+            return None;
+        }
+        // SAFETY: The ID was listed by the X server, so it refers to a window
+        let window: x::Window = unsafe { xcb::XidNew::new(window_id) };
+        let reply = self
+            .connection
+            .wait_for_reply(self.connection.send_request(&x::GetProperty {
+                delete: false,
+                window,
+                property: self.property,
+                r#type: x::ATOM_CARDINAL,
+                long_offset: 0,
+                long_length: 1,
+            }))
+            .ok()?;
+        reply.value::<u32>().first().copied()
+    }
+}
+
+/// Captures the game window, finding it again when it was closed or recreated
+pub struct WindowCapture {
+    matcher: WindowMatcher,
+    window: Option<Window>,
+    /// Size of the last captured frame, to notice resolution changes
+    size: Option<(u32, u32)>,
+}
+
+impl WindowCapture {
+    pub fn new(matcher: WindowMatcher) -> WindowCapture {
+        WindowCapture {
+            matcher,
+            window: None,
+            size: None,
+        }
+    }
+
+    /// Waits until a matching window exists, checking every `interval`
+    pub fn wait(
+        matcher: WindowMatcher,
+        interval: Duration,
+    ) -> Result<WindowCapture, anyhow::Error> {
+        let mut capture = WindowCapture::new(matcher);
+        let mut waiting = false;
+        while capture.find()?.is_none() {
+            if !waiting {
+                info!("Waiting for the {}", capture.matcher);
+                waiting = true;
+            }
+            sleep(interval);
+        }
+        Ok(capture)
+    }
+
+    /// Looks the window up again, returns `None` if there is no matching window
+    fn find(&mut self) -> Result<Option<&Window>, anyhow::Error> {
+        let pids = match self.matcher {
+            WindowMatcher::Pid(_) => Some(WindowPids::connect()?),
+            _ => None,
+        };
+        self.window = Window::all()?.into_iter().find(|window| {
+            let pid = pids.as_ref().and_then(|pids| pids.get(window.id()));
+            self.matcher.matches(window.title(), pid)
+        });
+        if let Some(window) = self.window.as_ref() {
+            debug!(
+                "Found {:?} ({}) at {}x{}",
+                window.title(),
+                window.app_name(),
+                window.width(),
+                window.height()
+            );
+        }
+        Ok(self.window.as_ref())
+    }
+
+    fn capture_window(&mut self) -> Result<RgbaImage, anyhow::Error> {
+        let window = match self.window.as_ref() {
+            Some(window) => window,
+            None => match self.find()? {
+                Some(window) => window,
+                None => bail!("No {} found", self.matcher),
+            },
+        };
+        Ok(window.capture_image()?)
+    }
+}
+
+impl CaptureSource for WindowCapture {
+    fn capture(&mut self) -> Result<DynamicImage, anyhow::Error> {
+        let frame = match self.capture_window() {
+            Ok(frame) => frame,
+            // The game may have restarted, so the window handle is stale
+            Err(err) => {
+                warn!(
+                    "Failed to capture the {}, looking it up again: {err:#}",
+                    self.matcher
+                );
+                self.window = None;
+                self.capture_window()?
+            }
+        };
+
+        let size = frame.dimensions();
+        match self.size.replace(size) {
+            Some(previous) if previous != size => info!(
+                "Resolution changed from {}x{} to {}x{}",
+                previous.0, previous.1, size.0, size.1
+            ),
+            _ => {}
+        }
+        Ok(DynamicImage::ImageRgba8(frame))
+    }
+
+    fn description(&self) -> String {
+        match self.window.as_ref() {
+            Some(window) => format!(
+                "window {:?} ({}x{})",
+                window.title(),
+                window.width(),
+                window.height()
+            ),
+            None => self.matcher.to_string(),
+        }
+    }
+}
+
+/// Captures everything shown on a monitor
+pub struct MonitorCapture {
+    monitor: Monitor,
+}
+
+impl MonitorCapture {
+    pub fn primary() -> Result<MonitorCapture, anyhow::Error> {
+        Self::select(None)
+    }
+
+    /// Selects a monitor by its index or name, or the primary monitor if `None`
+    pub fn select(selector: Option<&str>) -> Result<MonitorCapture, anyhow::Error> {
+        let monitors = Monitor::all()?;
+        let names: Vec<_> = monitors
+            .iter()
+            .map(|monitor| monitor.name().to_owned())
+            .collect();
+        let monitor = match selector {
+            None => monitors.into_iter().find(|monitor| monitor.is_primary()),
+            Some(selector) => match selector.parse::<usize>() {
+                Ok(index) => monitors.into_iter().nth(index),
+                Err(_) => monitors
+                    .into_iter()
+                    .find(|monitor| monitor.name().eq_ignore_ascii_case(selector)),
+            },
+        };
+        match monitor {
+            Some(monitor) => Ok(MonitorCapture { monitor }),
+            None => bail!(
+                "Monitor {} not found, available monitors: {}",
+                selector.unwrap_or("primary"),
+                names.join(", ")
+            ),
+        }
+    }
+}
+
+impl CaptureSource for MonitorCapture {
+    fn capture(&mut self) -> Result<DynamicImage, anyhow::Error> {
+        Ok(DynamicImage::ImageRgba8(self.monitor.capture_image()?))
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "monitor {} ({}x{})",
+            self.monitor.name(),
+            self.monitor.width(),
+            self.monitor.height()
+        )
+    }
+}
+
+/// Takes screenshots through the xdg-desktop-portal Screenshot interface
+pub struct PortalCapture {
+    runtime: tokio::runtime::Runtime,
+}
+
+impl PortalCapture {
+    pub fn new() -> Result<PortalCapture, anyhow::Error> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(PortalCapture { runtime })
+    }
+}
+
+impl CaptureSource for PortalCapture {
+    fn capture(&mut self) -> Result<DynamicImage, anyhow::Error> {
+        let requested = SystemTime::now();
+        let screenshot = self.runtime.block_on(async {
+            Screenshot::request()
+                .interactive(false)
+                .modal(false)
+                .send()
+                .await?
+                .response()
+        })?;
+        let uri = screenshot.uri();
+        let Ok(path) = uri.to_file_path() else {
+            bail!("The portal returned the screenshot as {uri}, which isn't a local file");
+        };
+        let image = Reader::open(&path)?
+            .with_guessed_format()?
+            .decode()
+            .with_context(|| format!("Failed to decode {}", path.display()));
+        // The portal saves every screenshot, usually to ~/Pictures, which would pile up.
+        // Files that were there before the request belong to the user and are kept
+        if created_since(&path, requested) {
+            if let Err(err) = remove_file(&path) {
+                warn!("Failed to remove {}: {err}", path.display());
+            }
+        } else {
+            debug!("Keeping {}, it existed before the request", path.display());
+        }
+        image
+    }
+
+    fn description(&self) -> String {
+        "screenshots from the desktop portal".to_string()
+    }
+}
+
+/// Whether the file at `path` was created at or after `time`
+fn created_since(path: &Path, time: SystemTime) -> bool {
+    let Ok(metadata) = path.metadata() else {
+        return false;
+    };
+    let created = metadata.created().or_else(|_| metadata.modified());
+    // File timestamps come from a coarser clock and can lag slightly behind
+    matches!(created, Ok(created) if created + Duration::from_secs(1) >= time)
+}
+
+/// Part of a capture, e.g. the game on one of several monitors captured at once
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Parses X11 style geometry, `<width>x<height>+<x>+<y>`
+impl FromStr for CaptureRegion {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<CaptureRegion, anyhow::Error> {
+        let parse = || {
+            let (size, position) = text.split_once('+')?;
+            let (width, height) = size.split_once('x')?;
+            let (x, y) = position.split_once('+')?;
+            Some(CaptureRegion {
+                x: x.parse().ok()?,
+                y: y.parse().ok()?,
+                width: width.parse().ok()?,
+                height: height.parse().ok()?,
+            })
+        };
+        match parse() {
+            Some(region) if region.width > 0 && region.height > 0 => Ok(region),
+            _ => bail!("Invalid capture region {text:?}, expected <width>x<height>+<x>+<y>"),
+        }
+    }
+}
+
+/// Crops every capture of another source to a region
+pub struct CroppedCapture<S: CaptureSource> {
+    source: S,
+    region: CaptureRegion,
+}
+
+impl<S: CaptureSource> CroppedCapture<S> {
+    pub fn new(source: S, region: CaptureRegion) -> CroppedCapture<S> {
+        CroppedCapture { source, region }
+    }
+}
+
+impl<S: CaptureSource> CaptureSource for CroppedCapture<S> {
+    fn capture(&mut self) -> Result<DynamicImage, anyhow::Error> {
+        let image = self.source.capture()?;
+        let CaptureRegion {
+            x,
+            y,
+            width,
+            height,
+        } = self.region;
+        let fits = x
+            .checked_add(width)
+            .is_some_and(|right| right <= image.width())
+            && y.checked_add(height)
+                .is_some_and(|bottom| bottom <= image.height());
+        if !fits {
+            bail!(
+                "Capture region {width}x{height}+{x}+{y} exceeds the captured {}x{}",
+                image.width(),
+                image.height()
+            );
+        }
+        Ok(image.crop_imm(x, y, width, height))
+    }
+
+    fn description(&self) -> String {
+        let CaptureRegion {
+            x,
+            y,
+            width,
+            height,
+        } = self.region;
+        format!("{width}x{height}+{x}+{y} of {}", self.source.description())
+    }
+}
+
+/// Uses the newest screenshot in a directory that wasn't used before
+pub struct DirectoryCapture {
+    directory: PathBuf,
+    used: HashSet<PathBuf>,
+}
+
+/// File extensions of the screenshots picked up by [`DirectoryCapture`]
+const SCREENSHOT_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "bmp"];
+
+impl DirectoryCapture {
+    /// Screenshots already in the directory are ignored
+    pub fn new(directory: &Path) -> Result<DirectoryCapture, anyhow::Error> {
+        let mut capture = DirectoryCapture {
+            directory: directory.to_owned(),
+            used: HashSet::new(),
+        };
+        capture.used = capture
+            .screenshots()?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        Ok(capture)
+    }
+
+    fn screenshots(&self) -> Result<Vec<(PathBuf, std::time::SystemTime)>, anyhow::Error> {
+        let mut screenshots = Vec::new();
+        for entry in read_dir(&self.directory)
+            .with_context(|| format!("Failed to read {}", self.directory.display()))?
+        {
+            let entry = entry?;
+            let path = entry.path();
+            let is_screenshot = path.extension().is_some_and(|extension| {
+                SCREENSHOT_EXTENSIONS
+                    .iter()
+                    .any(|known| extension.eq_ignore_ascii_case(known))
+            });
+            if is_screenshot {
+                screenshots.push((path, entry.metadata()?.modified()?));
+            }
+        }
+        Ok(screenshots)
+    }
+}
+
+impl CaptureSource for DirectoryCapture {
+    fn capture(&mut self) -> Result<DynamicImage, anyhow::Error> {
+        let Some((path, _)) = self
+            .screenshots()?
+            .into_iter()
+            .filter(|(path, _)| !self.used.contains(path))
+            .max_by_key(|(_, modified)| *modified)
+        else {
+            bail!("No new screenshot in {}", self.directory.display());
+        };
+        self.used.insert(path.clone());
+        Reader::open(&path)?
+            .with_guessed_format()?
+            .decode()
+            .with_context(|| format!("Failed to decode {}", path.display()))
+    }
+
+    fn description(&self) -> String {
+        format!("screenshots in {}", self.directory.display())
+    }
+}
+
+/// Reads a single image, e.g. from stdin, and returns it for every capture
+pub struct ReaderCapture<R: Read> {
+    reader: Option<R>,
+    image: Option<DynamicImage>,
+}
+
+impl<R: Read> ReaderCapture<R> {
+    pub fn new(reader: R) -> ReaderCapture<R> {
+        ReaderCapture {
+            reader: Some(reader),
+            image: None,
+        }
+    }
+}
+
+impl ReaderCapture<std::io::Stdin> {
+    pub fn stdin() -> ReaderCapture<std::io::Stdin> {
+        ReaderCapture::new(std::io::stdin())
+    }
+}
+
+impl<R: Read> CaptureSource for ReaderCapture<R> {
+    fn capture(&mut self) -> Result<DynamicImage, anyhow::Error> {
+        if let Some(reader) = self.reader.take() {
+            let mut bytes = Vec::new();
+            BufReader::new(reader).read_to_end(&mut bytes)?;
+            self.image = Some(image::load_from_memory(&bytes).context("Failed to decode image")?);
+        }
+        self.image.clone().context("Failed to read image")
+    }
+
+    fn description(&self) -> String {
+        "image from stdin".to_string()
+    }
+}
+
+/// Returns the given frames in order and fails once they run out, for tests
+#[derive(Default)]
+pub struct ScriptedCapture {
+    frames: VecDeque<DynamicImage>,
+    /// Number of captures attempted so far
+    pub captures: usize,
+}
+
+impl ScriptedCapture {
+    pub fn new(frames: impl IntoIterator<Item = DynamicImage>) -> ScriptedCapture {
+        ScriptedCapture {
+            frames: frames.into_iter().collect(),
+            captures: 0,
+        }
+    }
+}
+
+impl CaptureSource for ScriptedCapture {
+    fn capture(&mut self) -> Result<DynamicImage, anyhow::Error> {
+        self.captures += 1;
+        self.frames.pop_front().context("No frames left")
+    }
+
+    fn description(&self) -> String {
+        format!("{} scripted frames", self.frames.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, io::Cursor};
+
+    use image::{ImageOutputFormat, Rgb, RgbImage};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn frame(value: u8) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 3, Rgb([value; 3])))
+    }
+
+    #[test]
+    fn only_files_created_after_a_request_are_new() {
+        let directory = TempDir::new().unwrap();
+        let path = directory.path().join("Screenshot.png");
+        assert!(!created_since(&path, SystemTime::now()));
+
+        let before = SystemTime::now() - Duration::from_secs(60);
+        fs::write(&path, b"").unwrap();
+        assert!(created_since(&path, before));
+        assert!(!created_since(
+            &path,
+            SystemTime::now() + Duration::from_secs(60)
+        ));
+    }
+
+    #[test]
+    fn can_match_windows() {
+        assert!(WindowMatcher::Title("Warframe".to_string()).matches("Warframe", None));
+        assert!(!WindowMatcher::Title("Warframe".to_string()).matches("Warframe Launcher", None));
+
+        let regex = WindowMatcher::TitleRegex(Regex::new("^(Warframe|gamescope)$").unwrap());
+        assert!(regex.matches("gamescope", None));
+        assert!(!regex.matches("Warframe Launcher", None));
+
+        let pid = WindowMatcher::Pid(4242);
+        assert!(pid.matches("Warframe", Some(4242)));
+        assert!(!pid.matches("Warframe", Some(4243)));
+        assert!(!pid.matches("Warframe", None));
+    }
+
+    #[test]
+    fn can_parse_regions() {
+        assert_eq!(
+            "1920x1080+2560+0".parse::<CaptureRegion>().unwrap(),
+            CaptureRegion {
+                x: 2560,
+                y: 0,
+                width: 1920,
+                height: 1080
+            }
+        );
+        assert!("1920x1080".parse::<CaptureRegion>().is_err());
+        assert!("0x1080+0+0".parse::<CaptureRegion>().is_err());
+    }
+
+    #[test]
+    fn can_crop_captures() {
+        let frames = [
+            DynamicImage::new_rgb8(64, 32),
+            DynamicImage::new_rgb8(16, 16),
+        ];
+        let region = "32x16+24+8".parse().unwrap();
+        let mut capture = CroppedCapture::new(ScriptedCapture::new(frames), region);
+        let image = capture.capture().unwrap();
+        assert_eq!((image.width(), image.height()), (32, 16));
+        // Smaller frames than the region are an error
+        assert!(capture.capture().is_err());
+
+        let region = "4294967295x1+1+0".parse().unwrap();
+        let frames = [DynamicImage::new_rgb8(16, 16)];
+        let mut capture = CroppedCapture::new(ScriptedCapture::new(frames), region);
+        assert!(capture.capture().is_err());
+    }
+
+    #[test]
+    fn can_script_captures() {
+        let mut source = ScriptedCapture::new([frame(1), frame(2)]);
+        assert_eq!(source.capture().unwrap(), frame(1));
+        assert_eq!(source.capture().unwrap(), frame(2));
+        assert!(source.capture().is_err());
+        assert_eq!(source.captures, 3);
+    }
+
+    #[test]
+    fn can_capture_from_readers() {
+        let mut png = Vec::new();
+        frame(7)
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .unwrap();
+        let mut source = ReaderCapture::new(Cursor::new(png));
+        assert_eq!(source.capture().unwrap().to_rgb8(), frame(7).to_rgb8());
+        // Later captures reuse the image
+        assert_eq!(source.capture().unwrap().to_rgb8(), frame(7).to_rgb8());
+
+        assert!(ReaderCapture::new(Cursor::new(b"not an image"))
+            .capture()
+            .is_err());
+    }
+
+    #[test]
+    fn can_capture_new_screenshots() {
+        let temporary = TempDir::new().unwrap();
+        let directory = temporary.path();
+        frame(1).save(directory.join("old.png")).unwrap();
+
+        let mut source = DirectoryCapture::new(directory).unwrap();
+        assert!(source.capture().is_err());
+
+        frame(2).save(directory.join("new.png")).unwrap();
+        fs::write(directory.join("notes.txt"), "not a screenshot").unwrap();
+        assert_eq!(source.capture().unwrap().to_rgb8(), frame(2).to_rgb8());
+        assert!(source.capture().is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    burst::Burst,
+    capture::{CaptureKind, CaptureRegion},
+    ocr::{Localiser, OcrOptions},
+    preprocess::Preprocessing,
+    rewards::{RewardCatalogue, RewardOverride},
+    theme::Theme,
+    utils::DEFAULT_MAX_AGE,
+};
+
+/// Settings read from `$XDG_CONFIG_HOME/wfinfo/config.json`, every field is optional
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Base URL serving the `prices/` and `filtered_items/` endpoints, or a `file://` directory
+    pub data_url: Option<String>,
+    /// Directory containing `prices.json` and `filtered_items.json` to use instead of downloading
+    pub mirror: Option<PathBuf>,
+    /// Hours after which downloaded data is refreshed
+    pub cache_max_age: Option<u64>,
+    /// Values of non-prime rewards like Forma, keyed by reward name
+    pub rewards: HashMap<String, RewardOverride>,
+    /// Language the game is played in, e.g. "de"
+    pub locale: Option<String>,
+    /// How reward names are found in screenshots, `heuristic` or `template`
+    pub localiser: Option<Localiser>,
+    /// Steps applied to the reward name images before OCR
+    pub preprocessing: Preprocessing,
+    /// Reads every line of a reward name separately
+    pub split_lines: bool,
+    /// Reads how many of each reward are owned from the reward screen
+    pub read_status: bool,
+    /// Detected themes winning by a smaller share of pixels are double checked with the runner-up
+    pub theme_margin: Option<f32>,
+    /// Theme used instead of detecting it, e.g. a custom theme fitted by `wfinfo theme fit`
+    pub theme: Option<Theme>,
+    /// Where screenshots are taken from, `window`, `monitor`, `portal`, `directory` or `stdin`
+    pub capture: Option<CaptureKind>,
+    /// Directory the screenshots are saved to when capturing from a directory
+    pub screenshot_directory: Option<PathBuf>,
+    /// Index or name of the monitor captured when capturing a monitor, the primary one by default
+    pub monitor: Option<String>,
+    /// Part of every capture the game is shown in, e.g. one monitor of a capture spanning several
+    pub capture_region: Option<CaptureRegion>,
+    /// Frames captured per detection and how the rewards are read from them
+    pub burst: Burst,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/wfinfo`, falling back to `~/.config/wfinfo`
+    pub fn directory() -> PathBuf {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .unwrap_or_default()
+            .join("wfinfo")
+    }
+
+    pub fn path() -> PathBuf {
+        Self::directory().join("config.json")
+    }
+
+    /// Loads the config file, a missing file results in the default config
+    pub fn load() -> Result<Config, anyhow::Error> {
+        Self::load_from(&Self::path())
+    }
+
+    pub fn load_from(path: &Path) -> Result<Config, anyhow::Error> {
+        match read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .with_context(|| format!("Failed to parse config file {}", path.display())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn cache_max_age(&self) -> Duration {
+        self.cache_max_age.map_or(DEFAULT_MAX_AGE, |hours| {
+            Duration::from_secs(hours * 60 * 60)
+        })
+    }
+
+    /// The bundled reward catalogue with the configured values applied
+    pub fn reward_catalogue(&self) -> RewardCatalogue {
+        RewardCatalogue::bundled().with_overrides(&self.rewards)
+    }
+
+    /// The configured OCR settings, `localiser` overrides the configured one
+    pub fn ocr_options(&self, localiser: Option<Localiser>) -> OcrOptions {
+        OcrOptions {
+            localiser: localiser.or(self.localiser).unwrap_or_default(),
+            preprocessing: self.preprocessing.clone(),
+            split_lines: self.split_lines,
+            read_status: self.read_status,
+            theme_margin: self
+                .theme_margin
+                .unwrap_or(OcrOptions::default().theme_margin),
+        }
+    }
+}
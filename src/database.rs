@@ -1,76 +1,250 @@
-use std::{collections::HashMap, fs::read_to_string, path::Path};
+use std::{
+    collections::HashMap,
+    fs::{read_to_string, File},
+    io::{Read, Write},
+    path::Path,
+};
 
+use anyhow::{bail, Context};
 use levenshtein::levenshtein;
-use serde::Deserialize;
+use log::warn;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::{
+    drop_names::{part_kind, DropNames},
+    ocr::normalize_string,
+    rewards::RewardCatalogue,
+    sets::ItemSet,
     statistics::{self, Bucket},
     wfinfo_data::{
-        item_data::{EquipmentType, FilteredItems, Refinement, Relic, Relics},
+        item_data::{EquipmentType, FilteredItems, Rarity, Refinement, Relic, RelicTier, Relics},
         price_data::PriceItem,
     },
 };
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Database {
     items: Vec<Item>,
+    sets: Vec<ItemSet>,
     pub relics: Relics,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Item {
     pub name: String,
     pub drop_name: String,
     pub platinum: f32,
     pub ducats: usize,
+    #[serde(default)]
+    pub market: MarketStats,
+    /// Name of the set this item is a part of, e.g. "Octavia Prime Set"
+    #[serde(default)]
+    pub set: Option<String>,
+    #[serde(default)]
+    pub equipment_type: Option<EquipmentType>,
+    #[serde(default)]
+    pub vaulted: bool,
+    /// Which part of the set this is, e.g. "Neuroptics" or "Blueprint"
+    #[serde(default)]
+    pub part_kind: Option<String>,
+    /// Reward screen name in the configured game language, see [`crate::locale::Locale`]
+    #[serde(default)]
+    pub localised_name: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MarketStats {
+    pub yesterday_volume: Option<usize>,
+    pub today_volume: Option<usize>,
+    /// Whether more or less of the item is traded today than yesterday
+    pub trend: Trend,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Trend {
+    Rising,
+    Falling,
+    Stable,
+    #[default]
+    Unknown,
+}
+
+impl From<&PriceItem> for MarketStats {
+    fn from(price: &PriceItem) -> Self {
+        // The prices endpoint only has the traded volumes, no price history
+        let trend = match (price.yesterday_vol, price.today_vol) {
+            (Some(yesterday), Some(today)) if today as f32 > yesterday as f32 * 1.1 => {
+                Trend::Rising
+            }
+            (Some(yesterday), Some(today)) if (today as f32) < yesterday as f32 * 0.9 => {
+                Trend::Falling
+            }
+            (Some(_), Some(_)) => Trend::Stable,
+            _ => Trend::Unknown,
+        };
+
+        MarketStats {
+            yesterday_volume: price.yesterday_vol,
+            today_volume: price.today_vol,
+            trend,
+        }
+    }
+}
+
+/// Builds a [`Database`] item by item, e.g. for tests or when embedding the library
+#[derive(Clone, Debug, Default)]
+pub struct DatabaseBuilder {
+    items: Vec<Item>,
+    sets: Vec<ItemSet>,
+    relics: Relics,
+}
+
+const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot<T> {
+    version: u32,
+    database: T,
+}
+
+impl Item {
+    pub fn new(name: impl Into<String>, platinum: f32, ducats: usize) -> Item {
+        let name = name.into();
+        Item {
+            drop_name: name.clone(),
+            name,
+            platinum,
+            ducats,
+            market: MarketStats::default(),
+            set: None,
+            equipment_type: None,
+            vaulted: false,
+            part_kind: None,
+            localised_name: None,
+        }
+    }
+
+    /// Whether enough of this item sold yesterday to count on selling it,
+    /// items without volume data are assumed to be liquid
+    pub fn is_liquid(&self, min_volume: usize) -> bool {
+        match self.market.yesterday_volume {
+            Some(volume) => volume >= min_volume,
+            None => true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RelicDrop {
+    pub tier: RelicTier,
+    pub relic: String,
+    pub rarity: Rarity,
+    pub vaulted: bool,
+}
+
+impl RelicDrop {
+    pub fn chance(&self, refinement: Refinement) -> f32 {
+        self.rarity.chance(refinement)
+    }
 }
 
 impl Database {
     pub fn load_from_file(prices: Option<&Path>, filtered_items: Option<&Path>) -> Database {
         // download file from: https://api.warframestat.us/wfinfo/prices
-        let text = read_to_string(prices.unwrap_or_else(|| Path::new("prices.json"))).unwrap();
-        let price_list: Vec<PriceItem> = serde_json::from_str(&text).unwrap();
-        let price_table: HashMap<String, f32> = price_list
-            .into_iter()
-            .map(|item| (item.name, item.custom_avg))
-            .collect();
-
-        let text =
+        let prices = read_to_string(prices.unwrap_or_else(|| Path::new("prices.json"))).unwrap();
+        let filtered_items =
             read_to_string(filtered_items.unwrap_or_else(|| Path::new("filtered_items.json")))
                 .unwrap();
-        let mut json = serde_json::from_str(&text).unwrap();
+        Self::from_slices(prices.as_bytes(), filtered_items.as_bytes()).unwrap()
+    }
+
+    /// Loads `prices.json` and `filtered_items.json` from the given directory
+    pub fn load_from_directory(directory: &Path) -> Result<Database, anyhow::Error> {
+        let open = |name| {
+            let path = directory.join(name);
+            File::open(&path).with_context(|| format!("Failed to open {}", path.display()))
+        };
+        Self::from_readers(open("prices.json")?, open("filtered_items.json")?)
+    }
 
-        remove_empty_relics_from_json(&mut json);
+    /// Small database bundled with the repository from `test-data/`, meant for tests
+    #[cfg(any(test, feature = "test-fixtures"))]
+    pub fn from_fixture() -> Database {
+        Self::from_slices(
+            include_bytes!("../test-data/prices.json"),
+            include_bytes!("../test-data/filtered_items.json"),
+        )
+        .unwrap()
+    }
+
+    /// Parses the contents of `prices.json` and `filtered_items.json`
+    pub fn from_slices(prices: &[u8], filtered_items: &[u8]) -> Result<Database, anyhow::Error> {
+        let price_list: Vec<PriceItem> =
+            serde_json::from_slice(prices).context("Failed to parse price data")?;
 
-        let filtered_items: FilteredItems = serde_json::from_value(json).unwrap();
+        let mut json =
+            serde_json::from_slice(filtered_items).context("Failed to parse item data")?;
+        remove_empty_relics_from_json(&mut json)?;
+        let filtered_items: FilteredItems =
+            serde_json::from_value(json).context("Failed to parse item data")?;
 
-        let mut items: Vec<_> = filtered_items
+        Ok(Self::from_data(price_list, filtered_items))
+    }
+
+    pub fn from_readers(
+        mut prices: impl Read,
+        mut filtered_items: impl Read,
+    ) -> Result<Database, anyhow::Error> {
+        let mut price_bytes = Vec::new();
+        prices.read_to_end(&mut price_bytes)?;
+        let mut item_bytes = Vec::new();
+        filtered_items.read_to_end(&mut item_bytes)?;
+        Self::from_slices(&price_bytes, &item_bytes)
+    }
+
+    /// Builds the database from already parsed price and item data
+    pub fn from_data(price_list: Vec<PriceItem>, filtered_items: FilteredItems) -> Database {
+        Self::from_data_with_drop_names(price_list, filtered_items, DropNames::bundled())
+            .with_rewards(RewardCatalogue::bundled())
+    }
+
+    /// Builds the database using the given rules to derive reward screen names
+    pub fn from_data_with_drop_names(
+        price_list: Vec<PriceItem>,
+        filtered_items: FilteredItems,
+        drop_names: &DropNames,
+    ) -> Database {
+        for item in drop_names.validate(&filtered_items) {
+            warn!(
+                "Can't derive reward screen name for {} ({:?} part {:?}), using it as is",
+                item.name, item.equipment_type, item.part_kind
+            );
+        }
+
+        let price_table: HashMap<String, PriceItem> = price_list
+            .into_iter()
+            .map(|item| (item.name.clone(), item))
+            .collect();
+
+        let items: Vec<_> = filtered_items
             .eqmt
             .iter()
-            .flat_map(|(_name, equipment_item)| {
+            .flat_map(|(equipment_name, equipment_item)| {
+                let price_table = &price_table;
                 equipment_item
                     .parts
                     .iter()
-                    .filter_map(|(name, ducat_item)| {
-                        let item_is_part = name.ends_with("Systems")
-                            || name.ends_with("Neuroptics")
-                            || name.ends_with("Chassis")
-                            || name.ends_with("Harness")
-                            || name.ends_with("Wings");
-                        let drop_name = match equipment_item.item_type {
-                            EquipmentType::Warframes | EquipmentType::Archwing
-                                if item_is_part && !name.ends_with("Blueprint") =>
-                            {
-                                name.to_owned() + " Blueprint"
-                            }
-                            _ => name.to_owned(),
-                        };
-                        let platinum = *match price_table
+                    .filter_map(move |(name, ducat_item)| {
+                        let part_kind = part_kind(equipment_name, name);
+                        let drop_name = drop_names
+                            .drop_name(name, equipment_item.item_type, part_kind.as_deref())
+                            .unwrap_or_else(|| name.to_owned());
+                        let price = match price_table
                             .get(name)
                             .or_else(|| price_table.get(&format!("{name} Blueprint")))
                         {
-                            Some(plat) => plat,
+                            Some(price) => price,
                             None => {
                                 println!("Failed to find price for item: {name}");
                                 return None;
@@ -81,39 +255,69 @@ impl Database {
                         Some(Item {
                             name: name.to_string(),
                             drop_name,
-                            platinum,
+                            platinum: price.custom_avg,
                             ducats,
+                            market: price.into(),
+                            set: Some(format!("{equipment_name} Set")),
+                            equipment_type: Some(equipment_item.item_type),
+                            vaulted: equipment_item.vaulted,
+                            part_kind,
+                            localised_name: None,
                         })
                     })
             })
-            .chain(filtered_items.ignored_items.keys().map(|name| Item {
-                name: name.to_owned(),
-                drop_name: name.to_owned(),
-                platinum: 0.0,
-                ducats: 0,
-            }))
+            .chain(
+                filtered_items
+                    .ignored_items
+                    .keys()
+                    .map(|name| Item::new(name, 0.0, 0)),
+            )
             .collect();
 
-        if let Some(item) = items.iter_mut().find(|item| item.name == "Forma Blueprint") {
-            item.platinum = 35.0 / 3.0;
-        };
+        let mut sets: Vec<_> = filtered_items
+            .eqmt
+            .iter()
+            .map(|(name, equipment_item)| {
+                ItemSet::from_equipment(name, equipment_item, &price_table)
+            })
+            .collect();
+        sets.sort_by(|a, b| a.name.cmp(&b.name));
 
         let relics = filtered_items.relics;
 
-        Database { items, relics }
+        Database {
+            items,
+            sets,
+            relics,
+        }
     }
 
     pub fn find_item(&self, needle: &str, threshold: Option<usize>) -> Option<&Item> {
+        let localised_distance = |item: &Item| {
+            item.localised_name
+                .as_ref()
+                .map(|name| levenshtein(&normalize_string(name), needle))
+        };
         let best_match = self
             .items
             .iter()
             .filter(|item| !item.name.ends_with("Set"))
-            .min_by_key(|item| levenshtein(&item.drop_name, needle));
+            .min_by_key(|item| {
+                let distance = levenshtein(&item.drop_name, needle);
+                localised_distance(item).map_or(distance, |localised| localised.min(distance))
+            });
 
         best_match.and_then(|item| {
-            if levenshtein(&item.drop_name.replace(' ', ""), needle)
-                <= threshold.unwrap_or(item.drop_name.len() / 3)
-            {
+            let matches_drop_name = levenshtein(&item.drop_name.replace(' ', ""), needle)
+                <= threshold.unwrap_or(default_threshold(item.drop_name.len()));
+            let matches_localised_name = item
+                .localised_name
+                .as_ref()
+                .zip(localised_distance(item))
+                .is_some_and(|(name, distance)| {
+                    distance <= threshold.unwrap_or(default_threshold(name.chars().count()))
+                });
+            if matches_drop_name || matches_localised_name {
                 Some(item)
             } else {
                 None
@@ -121,68 +325,155 @@ impl Database {
         })
     }
 
+    /// Like [`Database::find_item`], but matches every line of `text` against the words of an
+    /// item name that would wrap onto that line. Differences within a line then can't be made up
+    /// for by another line, e.g. "Systems" on the first line never matches "Neuroptics".
+    pub fn find_item_multiline(&self, text: &str, threshold: Option<usize>) -> Option<&Item> {
+        let lines: Vec<_> = text
+            .lines()
+            .map(normalize_string)
+            .filter(|line| !line.is_empty())
+            .collect();
+        if lines.len() <= 1 {
+            return self.find_item(&lines.concat(), threshold);
+        }
+
+        let distance = |name: &str| {
+            let words: Vec<_> = name.split_whitespace().map(normalize_string).collect();
+            wrapped_distance(&words, &lines)
+        };
+        let (item, distance, name_length) = self
+            .items
+            .iter()
+            .filter(|item| !item.name.ends_with("Set"))
+            .flat_map(|item| {
+                std::iter::once(&item.drop_name)
+                    .chain(item.localised_name.as_ref())
+                    .filter_map(move |name| {
+                        Some((
+                            item,
+                            distance(name)?,
+                            normalize_string(name).chars().count(),
+                        ))
+                    })
+            })
+            .min_by_key(|(_, distance, _)| *distance)?;
+
+        (distance <= threshold.unwrap_or(default_threshold(name_length))).then_some(item)
+    }
+
     pub fn find_item_exact(&self, needle: &str) -> Option<&Item> {
         self.items.iter().find(|item| item.name == needle)
     }
 
+    pub fn builder() -> DatabaseBuilder {
+        DatabaseBuilder::default()
+    }
+
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    pub(crate) fn items_mut(&mut self) -> &mut Vec<Item> {
+        &mut self.items
+    }
+
+    /// Writes the database in a compact format that can be loaded using [`Database::read_snapshot`]
+    pub fn write_snapshot(&self, writer: impl Write) -> Result<(), anyhow::Error> {
+        serde_json::to_writer(
+            writer,
+            &Snapshot {
+                version: SNAPSHOT_VERSION,
+                database: self,
+            },
+        )?;
+        Ok(())
+    }
+
+    pub fn read_snapshot(reader: impl Read) -> Result<Database, anyhow::Error> {
+        let snapshot: Snapshot<Database> = serde_json::from_reader(reader)?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            bail!("Unsupported snapshot version {}", snapshot.version);
+        }
+        Ok(snapshot.database)
+    }
+
+    pub fn sets(&self) -> &[ItemSet] {
+        &self.sets
+    }
+
+    pub fn find_set(&self, name: &str) -> Option<&ItemSet> {
+        self.sets.iter().find(|set| set.name == name)
+    }
+
+    /// The set the given item is a part of
+    pub fn set_of(&self, item_name: &str) -> Option<&ItemSet> {
+        self.sets.iter().find(|set| set.contains(item_name))
+    }
+
+    /// Fuzzy lookup for user provided item names, ignoring case and whitespace
+    pub fn find_item_by_name(&self, needle: &str) -> Option<&Item> {
+        let simplify = |name: &str| name.to_lowercase().replace(' ', "");
+        let needle = simplify(needle);
+
+        self.items
+            .iter()
+            .filter(|item| !item.name.ends_with("Set"))
+            .map(|item| {
+                let distance = levenshtein(&simplify(&item.name), &needle)
+                    .min(levenshtein(&simplify(&item.drop_name), &needle));
+                (item, distance)
+            })
+            .min_by_key(|(_item, distance)| *distance)
+            .filter(|(item, distance)| *distance <= default_threshold(item.name.len()))
+            .map(|(item, _distance)| item)
+    }
+
+    /// Lists every relic that contains the given item, sorted by tier and relic name
+    pub fn relics_dropping(&self, item_name: &str) -> Vec<RelicDrop> {
+        let mut drops: Vec<_> = self
+            .relics
+            .iter()
+            .flat_map(|(tier, name, relic)| {
+                relic
+                    .drops()
+                    .into_iter()
+                    .filter(|(drop, _rarity)| *drop == item_name)
+                    .map(move |(_drop, rarity)| RelicDrop {
+                        tier,
+                        relic: name.to_owned(),
+                        rarity,
+                        vaulted: relic.vaulted,
+                    })
+            })
+            .collect();
+        drops.sort_by(|a, b| a.tier.cmp(&b.tier).then_with(|| a.relic.cmp(&b.relic)));
+        drops
+    }
+
     fn relic_to_bucket(&self, relic: &Relic, refinement: Refinement) -> Bucket {
-        let common_chance = refinement.common_chance();
-        let uncommon_chance = refinement.uncommon_chance();
-        let rare_chance = refinement.rare_chance();
-
-        let item_names = [
-            (&relic.common1, common_chance),
-            (&relic.common2, common_chance),
-            (&relic.common3, common_chance),
-            (&relic.uncommon1, uncommon_chance),
-            (&relic.uncommon2, uncommon_chance),
-            (&relic.rare1, rare_chance),
-        ];
-        let items = item_names
+        let items = relic
+            .drops()
             .into_iter()
-            .map(|(name, chance)| statistics::Item {
+            .map(|(name, rarity)| statistics::Item {
                 value: self
                     .find_item_exact(name)
                     .unwrap_or_else(|| panic!("Failed to find item {} in database", name))
                     .platinum,
-                probability: chance,
+                probability: rarity.chance(refinement),
             })
             .collect();
         Bucket::new(items)
     }
 
     pub fn single_relic_value(&self, relic: &Relic, refinement: Refinement) -> f32 {
-        let common_chance = refinement.common_chance();
-        let uncommon_chance = refinement.uncommon_chance();
-        let rare_chance = refinement.rare_chance();
-
-        let value = 0.0
-            + self.find_item_exact(&relic.common1).unwrap().platinum * common_chance
-            + self.find_item_exact(&relic.common2).unwrap().platinum * common_chance
-            + self.find_item_exact(&relic.common3).unwrap().platinum * common_chance
-            + self.find_item_exact(&relic.uncommon1).unwrap().platinum * uncommon_chance
-            + self.find_item_exact(&relic.uncommon2).unwrap().platinum * uncommon_chance
-            + self.find_item_exact(&relic.rare1).unwrap().platinum * rare_chance;
-
-        let item_names = [
-            (&relic.common1, common_chance),
-            (&relic.common2, common_chance),
-            (&relic.common3, common_chance),
-            (&relic.uncommon1, uncommon_chance),
-            (&relic.uncommon2, uncommon_chance),
-            (&relic.rare1, rare_chance),
-        ];
-        let value2: f32 = item_names
+        relic
+            .drops()
             .into_iter()
-            .map(|(name, chance)| {
-                let plat = self.find_item_exact(name).unwrap().platinum;
-                println!("{plat} * {chance}");
-                plat * chance
+            .map(|(name, rarity)| {
+                self.find_item_exact(name).unwrap().platinum * rarity.chance(refinement)
             })
-            .sum();
-        println!("{value} vs {value2}");
-
-        value
+            .sum()
     }
 
     pub fn shared_relic_value(
@@ -201,18 +492,9 @@ impl Database {
         refinement: Refinement,
         _number_of_relics: u32,
     ) -> f32 {
-        let common_chance = refinement.common_chance();
-        let uncommon_chance = refinement.uncommon_chance();
-        let rare_chance = refinement.rare_chance();
-
-        let items = [
-            (&relic.common1, common_chance),
-            (&relic.common2, common_chance),
-            (&relic.common3, common_chance),
-            (&relic.uncommon1, uncommon_chance),
-            (&relic.uncommon2, uncommon_chance),
-            (&relic.rare1, rare_chance),
-        ];
+        let items = relic
+            .drops()
+            .map(|(name, rarity)| (name, rarity.chance(refinement)));
 
         let mut value = 0.0;
         for item1 in items.iter() {
@@ -237,13 +519,88 @@ impl Database {
     }
 }
 
-fn remove_empty_relics_from_json(value: &mut Value) {
-    let relics = &mut value["relics"];
-    for (_, kind) in relics.as_object_mut().unwrap() {
+impl DatabaseBuilder {
+    /// Adds an item, replacing any previously added item with the same name
+    pub fn add_item(mut self, item: Item) -> Self {
+        self.items.retain(|existing| existing.name != item.name);
+        self.items.push(item);
+        self
+    }
+
+    /// Adds a set, replacing any previously added set with the same name
+    pub fn add_set(mut self, set: ItemSet) -> Self {
+        self.sets.retain(|existing| existing.name != set.name);
+        self.sets.push(set);
+        self
+    }
+
+    pub fn add_relic(mut self, tier: RelicTier, name: impl Into<String>, relic: Relic) -> Self {
+        self.relics.tier_mut(tier).insert(name.into(), relic);
+        self
+    }
+
+    /// Sets the price of the set or item with the given name, adding the item if it is unknown
+    pub fn set_price(mut self, name: &str, platinum: f32) -> Self {
+        if let Some(set) = self.sets.iter_mut().find(|set| set.name == name) {
+            set.platinum = Some(platinum);
+        } else if let Some(item) = self.items.iter_mut().find(|item| item.name == name) {
+            item.platinum = platinum;
+        } else {
+            self.items.push(Item::new(name, platinum, 0));
+        }
+        self
+    }
+
+    pub fn build(mut self) -> Database {
+        self.sets.sort_by(|a, b| a.name.cmp(&b.name));
+        Database {
+            items: self.items,
+            sets: self.sets,
+            relics: self.relics,
+        }
+    }
+}
+
+/// Names shorter than this, like "Kuva" or "Endo", only match exactly, as any tolerance would let
+/// them match short OCR noise
+const MIN_FUZZY_NAME_LENGTH: usize = 6;
+
+/// Largest edit distance at which text still matches a name of the given length
+fn default_threshold(name_length: usize) -> usize {
+    if name_length < MIN_FUZZY_NAME_LENGTH {
+        0
+    } else {
+        name_length / 3
+    }
+}
+
+/// Smallest total distance between the lines and the words wrapped onto that many lines,
+/// `None` if there are fewer words than lines
+fn wrapped_distance(words: &[String], lines: &[String]) -> Option<usize> {
+    match lines {
+        [] => None,
+        [line] => (!words.is_empty()).then(|| levenshtein(&words.concat(), line)),
+        [line, rest @ ..] => (1..words.len())
+            .filter_map(|split| {
+                Some(
+                    levenshtein(&words[..split].concat(), line)
+                        + wrapped_distance(&words[split..], rest)?,
+                )
+            })
+            .min(),
+    }
+}
+
+fn remove_empty_relics_from_json(value: &mut Value) -> Result<(), anyhow::Error> {
+    let relics = value["relics"]
+        .as_object_mut()
+        .context("Item data contains no relics")?;
+    for (_, kind) in relics {
         kind.as_object_mut()
-            .unwrap()
+            .context("Invalid relic list")?
             .retain(|_name, relic| serde_json::from_value::<Relic>(relic.clone()).is_ok());
     }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -254,12 +611,12 @@ mod test {
 
     #[test]
     pub fn can_load_database() {
-        Database::load_from_file(None, None);
+        Database::from_fixture();
     }
 
     #[test]
     pub fn can_find_items() {
-        let db = Database::load_from_file(None, None);
+        let db = Database::from_fixture();
 
         let item = db
             .find_item("TitaniaPrimeBlueprint", Some(0))
@@ -274,7 +631,7 @@ mod test {
 
     #[test]
     pub fn can_find_fuzzy_items() {
-        let db = Database::load_from_file(None, None);
+        let db = Database::from_fixture();
 
         let item = db
             .find_item("Akstlett Prlme Recver", None)
@@ -292,9 +649,174 @@ mod test {
         assert_eq!(item.name, "Octavia Prime Systems");
     }
 
+    #[test]
+    pub fn can_find_multiline_items() {
+        let db = Database::from_fixture();
+
+        let item = db
+            .find_item_multiline("Oclavia Prime Syslems\nBlueprint\n", None)
+            .expect("Failed to fuzzy find Octavia Prime Systems in database");
+        assert_eq!(item.name, "Octavia Prime Systems");
+
+        let item = db
+            .find_item_multiline("Octavia Prime Neuroptlcs\nBlueprnt", None)
+            .expect("Failed to fuzzy find Octavia Prime Neuroptics in database");
+        assert_eq!(item.name, "Octavia Prime Neuroptics");
+
+        let item = db
+            .find_item_multiline("Akstlett Prlme Recver\n", None)
+            .expect("Failed to fuzzy find Akstiletto Prime Receiver in database");
+        assert_eq!(item.name, "Akstiletto Prime Receiver");
+
+        let item = db
+            .find_item_multiline("Octavia Prime\nChassis Blueprint", Some(0))
+            .expect("Failed to find Octavia Prime Chassis wrapped after Prime");
+        assert_eq!(item.name, "Octavia Prime Chassis");
+    }
+
+    #[test]
+    pub fn can_load_market_data() {
+        let db = Database::from_fixture();
+
+        let item = db.find_item_exact("Octavia Prime Systems").unwrap();
+        assert!(item.market.yesterday_volume.is_some());
+        assert!(item.market.today_volume.is_some());
+
+        let market = &db.find_item_exact("Akjagara Prime Barrel").unwrap().market;
+        assert_eq!(market.yesterday_volume, Some(52));
+        assert_eq!(market.today_volume, Some(91));
+        assert_eq!(market.trend, Trend::Rising);
+        let market = &db
+            .find_item_exact("Akjagara Prime Receiver")
+            .unwrap()
+            .market;
+        assert_eq!(market.trend, Trend::Falling);
+        let market = &db.find_item_exact("Akbronco Prime Link").unwrap().market;
+        assert_eq!(market.trend, Trend::Stable);
+
+        let price: PriceItem =
+            serde_json::from_str(r#"{"name": "Lex Prime Set", "custom_avg": "20.5"}"#).unwrap();
+        assert_eq!(MarketStats::from(&price).trend, Trend::Unknown);
+    }
+
+    #[test]
+    pub fn can_read_item_details() {
+        let db = Database::from_fixture();
+
+        let item = db.find_item_exact("Octavia Prime Systems").unwrap();
+        assert_eq!(item.set.as_deref(), Some("Octavia Prime Set"));
+        assert_eq!(item.equipment_type, Some(EquipmentType::Warframes));
+        assert!(!item.vaulted);
+        assert_eq!(item.part_kind.as_deref(), Some("Systems"));
+        assert!(db.find_set(item.set.as_ref().unwrap()).is_some());
+
+        let item = db.find_item_exact("Paris Prime Upper Limb").unwrap();
+        assert_eq!(item.equipment_type, Some(EquipmentType::Primary));
+        assert!(item.vaulted);
+        assert_eq!(item.part_kind.as_deref(), Some("Upper Limb"));
+
+        let item = db.find_item_exact("Forma Blueprint").unwrap();
+        assert_eq!(item.set, None);
+        assert_eq!(item.equipment_type, None);
+    }
+
+    #[test]
+    pub fn can_find_relics_dropping_item() {
+        let db = Database::from_fixture();
+
+        let item = db
+            .find_item_by_name("octavia prime systems")
+            .expect("Failed to find Octavia Prime Systems in database");
+        assert_eq!(item.name, "Octavia Prime Systems");
+
+        let drops = db.relics_dropping(&item.name);
+        assert!(!drops.is_empty());
+        for drop in drops {
+            let relic = &db.relics.tier(drop.tier)[&drop.relic];
+            assert_eq!(relic.vaulted, drop.vaulted);
+            assert!(relic.drops().contains(&(item.name.as_str(), drop.rarity)));
+        }
+    }
+
+    #[test]
+    pub fn can_build_database() {
+        let relic = Relic {
+            vaulted: false,
+            rare1: "Lex Prime Receiver".to_string(),
+            uncommon1: "Lex Prime Barrel".to_string(),
+            uncommon2: "Forma Blueprint".to_string(),
+            common1: "Forma Blueprint".to_string(),
+            common2: "Forma Blueprint".to_string(),
+            common3: "Forma Blueprint".to_string(),
+        };
+        let db = Database::builder()
+            .add_item(Item::new("Lex Prime Receiver", 40.0, 45))
+            .add_item(Item::new("Lex Prime Barrel", 5.0, 15))
+            .add_item(Item::new("Forma Blueprint", 0.0, 0))
+            .add_relic(RelicTier::Lith, "L1", relic)
+            .set_price("Forma Blueprint", 12.0)
+            .build();
+
+        assert_eq!(db.items().len(), 3);
+        assert_eq!(
+            db.find_item_exact("Forma Blueprint").unwrap().platinum,
+            12.0
+        );
+        assert_eq!(
+            db.relics_dropping("Lex Prime Receiver")[0].rarity,
+            Rarity::Rare
+        );
+        assert_relative_eq!(
+            db.shared_relic_value(&db.relics.lith["L1"], Refinement::Intact, 1),
+            40.0 * 0.02 + 5.0 * 0.11 + 12.0 * (0.11 + 3.0 * 0.2533),
+            epsilon = 0.001
+        );
+    }
+
+    #[test]
+    pub fn can_load_from_slices() {
+        let db = Database::from_slices(
+            include_bytes!("../test-data/prices.json"),
+            include_bytes!("../test-data/filtered_items.json"),
+        )
+        .unwrap();
+        assert_eq!(db.items().len(), Database::from_fixture().items().len());
+
+        assert!(Database::from_slices(b"[]", b"{}").is_err());
+        assert!(Database::from_readers(&b"{}"[..], &b"{}"[..]).is_err());
+    }
+
+    #[test]
+    pub fn can_load_from_directory() {
+        let db = Database::load_from_directory(Path::new("test-data")).unwrap();
+        assert_eq!(db.items().len(), Database::from_fixture().items().len());
+
+        assert!(Database::load_from_directory(Path::new("test-images")).is_err());
+    }
+
+    #[test]
+    pub fn can_round_trip_snapshots() {
+        let db = Database::from_fixture();
+
+        let mut snapshot = Vec::new();
+        db.write_snapshot(&mut snapshot).unwrap();
+        let loaded = Database::read_snapshot(&snapshot[..]).unwrap();
+
+        assert_eq!(loaded.items().len(), db.items().len());
+        assert_eq!(loaded.sets().len(), db.sets().len());
+        assert_eq!(
+            loaded.relics_dropping("Octavia Prime Systems"),
+            db.relics_dropping("Octavia Prime Systems")
+        );
+        let item = loaded.find_item_exact("Octavia Prime Systems").unwrap();
+        assert_eq!(item.market, db.find_item_exact(&item.name).unwrap().market);
+
+        assert!(Database::read_snapshot(&br#"{"version": 0, "database": {}}"#[..]).is_err());
+    }
+
     #[test]
     fn validate_shared_relic_values() {
-        let database = Database::load_from_file(None, None);
+        let database = Database::from_fixture();
 
         for (name, relic) in database.relics.lith.iter() {
             println!("{} {:#?}", name, relic);
@@ -0,0 +1,212 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{database::Database, wfinfo_data::item_data::RelicTier};
+
+/// Differences between two snapshots of the price and item data
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DatabaseDiff {
+    pub new_items: Vec<String>,
+    pub removed_items: Vec<String>,
+    pub new_relics: Vec<(RelicTier, String)>,
+    pub removed_relics: Vec<(RelicTier, String)>,
+    pub vaulted_relics: Vec<(RelicTier, String)>,
+    pub unvaulted_relics: Vec<(RelicTier, String)>,
+    /// Price changes of every item and set known to both snapshots
+    pub price_changes: Vec<PriceChange>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriceChange {
+    pub name: String,
+    pub old: f32,
+    pub new: f32,
+}
+
+impl PriceChange {
+    pub fn absolute(&self) -> f32 {
+        self.new - self.old
+    }
+
+    /// Change relative to the old price, `None` if the old price was zero
+    pub fn relative(&self) -> Option<f32> {
+        (self.old != 0.0).then(|| (self.new - self.old) / self.old)
+    }
+}
+
+impl DatabaseDiff {
+    pub fn new(old: &Database, new: &Database) -> DatabaseDiff {
+        let old_prices = prices(old);
+        let new_prices = prices(new);
+
+        let old_items: BTreeSet<_> = old.items().iter().map(|item| &item.name).collect();
+        let new_items: BTreeSet<_> = new.items().iter().map(|item| &item.name).collect();
+
+        let old_relics: HashMap<_, _> = old
+            .relics
+            .iter()
+            .map(|(tier, name, relic)| ((tier, name), relic.vaulted))
+            .collect();
+        let new_relics: HashMap<_, _> = new
+            .relics
+            .iter()
+            .map(|(tier, name, relic)| ((tier, name), relic.vaulted))
+            .collect();
+
+        let mut new_relic_names = Vec::new();
+        let mut vaulted_relics = Vec::new();
+        let mut unvaulted_relics = Vec::new();
+        for (&(tier, name), &vaulted) in new_relics.iter() {
+            let relic = (tier, name.to_owned());
+            match old_relics.get(&(tier, name)) {
+                None => new_relic_names.push(relic),
+                Some(false) if vaulted => vaulted_relics.push(relic),
+                Some(true) if !vaulted => unvaulted_relics.push(relic),
+                Some(_) => {}
+            }
+        }
+        let mut removed_relics: Vec<_> = old_relics
+            .keys()
+            .filter(|key| !new_relics.contains_key(key))
+            .map(|&(tier, name)| (tier, name.to_owned()))
+            .collect();
+        for relics in [
+            &mut new_relic_names,
+            &mut removed_relics,
+            &mut vaulted_relics,
+            &mut unvaulted_relics,
+        ] {
+            relics.sort();
+        }
+
+        let mut price_changes: Vec<_> = new_prices
+            .iter()
+            .filter_map(|(name, new)| {
+                let old = old_prices.get(name)?;
+                (old != new).then(|| PriceChange {
+                    name: name.to_string(),
+                    old: *old,
+                    new: *new,
+                })
+            })
+            .collect();
+        price_changes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        DatabaseDiff {
+            new_items: new_items
+                .difference(&old_items)
+                .map(|name| name.to_string())
+                .collect(),
+            removed_items: old_items
+                .difference(&new_items)
+                .map(|name| name.to_string())
+                .collect(),
+            new_relics: new_relic_names,
+            removed_relics,
+            vaulted_relics,
+            unvaulted_relics,
+            price_changes,
+        }
+    }
+
+    /// The `count` largest price changes in platinum, in either direction
+    pub fn movers_by_absolute_change(&self, count: usize) -> Vec<&PriceChange> {
+        let mut movers: Vec<_> = self.price_changes.iter().collect();
+        movers.sort_by(|a, b| b.absolute().abs().total_cmp(&a.absolute().abs()));
+        movers.truncate(count);
+        movers
+    }
+
+    /// The `count` largest price changes relative to the old price, in either direction
+    pub fn movers_by_relative_change(&self, count: usize) -> Vec<&PriceChange> {
+        let mut movers: Vec<_> = self
+            .price_changes
+            .iter()
+            .filter(|change| change.relative().is_some())
+            .collect();
+        movers.sort_by(|a, b| {
+            let relative = |change: &PriceChange| change.relative().unwrap_or(0.0).abs();
+            relative(b).total_cmp(&relative(a))
+        });
+        movers.truncate(count);
+        movers
+    }
+}
+
+fn prices(database: &Database) -> HashMap<&str, f32> {
+    database
+        .items()
+        .iter()
+        .map(|item| (item.name.as_str(), item.platinum))
+        .chain(
+            database
+                .sets()
+                .iter()
+                .filter_map(|set| Some((set.name.as_str(), set.platinum?))),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{database::Item, wfinfo_data::item_data::Relic};
+
+    use super::*;
+
+    fn relic(vaulted: bool) -> Relic {
+        Relic {
+            vaulted,
+            rare1: "Lex Prime Receiver".to_string(),
+            uncommon1: "Lex Prime Barrel".to_string(),
+            uncommon2: "Forma Blueprint".to_string(),
+            common1: "Forma Blueprint".to_string(),
+            common2: "Forma Blueprint".to_string(),
+            common3: "Forma Blueprint".to_string(),
+        }
+    }
+
+    #[test]
+    fn can_diff_databases() {
+        let old = Database::builder()
+            .add_item(Item::new("Lex Prime Receiver", 40.0, 45))
+            .add_item(Item::new("Lex Prime Barrel", 5.0, 15))
+            .add_item(Item::new("Forma Blueprint", 12.0, 0))
+            .add_item(Item::new("Soma Prime Stock", 10.0, 15))
+            .add_relic(RelicTier::Lith, "L1", relic(false))
+            .add_relic(RelicTier::Meso, "L2", relic(true))
+            .add_relic(RelicTier::Neo, "L3", relic(false))
+            .build();
+        let new = Database::builder()
+            .add_item(Item::new("Lex Prime Receiver", 30.0, 45))
+            .add_item(Item::new("Lex Prime Barrel", 10.0, 15))
+            .add_item(Item::new("Forma Blueprint", 12.0, 0))
+            .add_item(Item::new("Vasto Prime Barrel", 3.0, 15))
+            .add_relic(RelicTier::Lith, "L1", relic(true))
+            .add_relic(RelicTier::Meso, "L2", relic(false))
+            .add_relic(RelicTier::Axi, "L4", relic(false))
+            .build();
+
+        let diff = DatabaseDiff::new(&old, &new);
+        assert_eq!(diff.new_items, ["Vasto Prime Barrel"]);
+        assert_eq!(diff.removed_items, ["Soma Prime Stock"]);
+        assert_eq!(diff.new_relics, [(RelicTier::Axi, "L4".to_string())]);
+        assert_eq!(diff.removed_relics, [(RelicTier::Neo, "L3".to_string())]);
+        assert_eq!(diff.vaulted_relics, [(RelicTier::Lith, "L1".to_string())]);
+        assert_eq!(diff.unvaulted_relics, [(RelicTier::Meso, "L2".to_string())]);
+        assert_eq!(diff.price_changes.len(), 2);
+
+        let absolute = diff.movers_by_absolute_change(1);
+        assert_eq!(absolute[0].name, "Lex Prime Receiver");
+        assert_eq!(absolute[0].absolute(), -10.0);
+
+        let relative = diff.movers_by_relative_change(5);
+        assert_eq!(relative[0].name, "Lex Prime Barrel");
+        assert_eq!(relative[0].relative(), Some(1.0));
+        assert_eq!(relative.len(), 2);
+    }
+
+    #[test]
+    fn identical_databases_have_no_differences() {
+        let db = Database::from_fixture();
+        assert_eq!(DatabaseDiff::new(&db, &db), DatabaseDiff::default());
+    }
+}
@@ -0,0 +1,225 @@
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+use crate::{
+    versioned::{self, Versioned},
+    wfinfo_data::item_data::{EquipmentType, FilteredItems},
+};
+
+lazy_static! {
+    static ref BUNDLED: DropNames = DropNames::from_json(include_str!("../data/drop_names.json"))
+        .expect("Bundled drop name data is invalid");
+}
+
+/// Rules mapping market item names to the names shown on the reward screen
+#[derive(Clone, Debug, Deserialize)]
+pub struct DropNames {
+    pub version: u32,
+    pub rules: Vec<DropNameRule>,
+}
+
+impl Versioned for DropNames {
+    const VERSION: u32 = 1;
+    const KIND: &'static str = "drop name data";
+}
+
+/// Appends `suffix` to the names of the listed parts of the listed equipment types
+#[derive(Clone, Debug, Deserialize)]
+pub struct DropNameRule {
+    pub equipment_types: Vec<EquipmentType>,
+    pub parts: Vec<String>,
+    #[serde(default)]
+    pub suffix: String,
+}
+
+/// A part whose reward screen name isn't covered by any rule
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnresolvedItem {
+    pub name: String,
+    pub equipment_type: EquipmentType,
+    pub part_kind: Option<String>,
+}
+
+impl DropNames {
+    /// The rules shipped in `data/drop_names.json`
+    pub fn bundled() -> &'static DropNames {
+        &BUNDLED
+    }
+
+    pub fn from_json(text: &str) -> Result<DropNames, anyhow::Error> {
+        versioned::from_json(text)
+    }
+
+    /// Name shown on the reward screen for the given part, `None` if no rule covers it.
+    ///
+    /// Parts without a known part kind, e.g. as their name doesn't start with the equipment name,
+    /// use the first rule listing a part their name ends with.
+    pub fn drop_name(
+        &self,
+        name: &str,
+        equipment_type: EquipmentType,
+        part_kind: Option<&str>,
+    ) -> Option<String> {
+        let rules = || {
+            self.rules
+                .iter()
+                .filter(|rule| rule.equipment_types.contains(&equipment_type))
+        };
+        let ends_with = |part: &str| {
+            name.strip_suffix(part)
+                .is_some_and(|rest| rest.is_empty() || rest.ends_with(' '))
+        };
+        part_kind
+            .and_then(|part_kind| {
+                rules().find(|rule| rule.parts.iter().any(|part| part == part_kind))
+            })
+            .or_else(|| rules().find(|rule| rule.parts.iter().any(|part| ends_with(part))))
+            .map(|rule| format!("{name}{}", rule.suffix))
+    }
+
+    /// Lists every equipment part whose reward screen name can't be derived
+    pub fn validate(&self, filtered_items: &FilteredItems) -> Vec<UnresolvedItem> {
+        let mut unresolved: Vec<_> = filtered_items
+            .eqmt
+            .iter()
+            .flat_map(|(equipment_name, equipment_item)| {
+                equipment_item.parts.keys().filter_map(move |name| {
+                    let part_kind = part_kind(equipment_name, name);
+                    self.drop_name(name, equipment_item.item_type, part_kind.as_deref())
+                        .is_none()
+                        .then(|| UnresolvedItem {
+                            name: name.to_owned(),
+                            equipment_type: equipment_item.item_type,
+                            part_kind,
+                        })
+                })
+            })
+            .collect();
+        unresolved.sort_by(|a, b| a.name.cmp(&b.name));
+        unresolved
+    }
+}
+
+/// The part of an item name following its equipment name, e.g. "Systems" for "Octavia Prime Systems"
+pub(crate) fn part_kind(equipment_name: &str, name: &str) -> Option<String> {
+    name.strip_prefix(equipment_name)
+        .map(|part| part.trim().to_owned())
+        .filter(|part| !part.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::wfinfo_data::item_data::{DucatItem, EquipmentItem, Relics};
+
+    use super::*;
+
+    #[test]
+    fn can_derive_drop_names() {
+        let drop_names = DropNames::bundled();
+        assert_eq!(
+            drop_names.drop_name(
+                "Octavia Prime Systems",
+                EquipmentType::Warframes,
+                Some("Systems")
+            ),
+            Some("Octavia Prime Systems Blueprint".to_string())
+        );
+        assert_eq!(
+            drop_names.drop_name(
+                "Octavia Prime Systems Blueprint",
+                EquipmentType::Warframes,
+                Some("Systems Blueprint")
+            ),
+            Some("Octavia Prime Systems Blueprint".to_string())
+        );
+        assert_eq!(
+            drop_names.drop_name(
+                "Helios Prime Systems",
+                EquipmentType::Sentinels,
+                Some("Systems")
+            ),
+            Some("Helios Prime Systems".to_string())
+        );
+    }
+
+    #[test]
+    fn reports_unresolved_items() {
+        let json: serde_json::Value =
+            serde_json::from_slice(include_bytes!("../test-data/filtered_items.json")).unwrap();
+        let mut filtered_items = FilteredItems {
+            errors: Vec::new(),
+            relics: Relics::default(),
+            eqmt: serde_json::from_value(json["eqmt"].clone()).unwrap(),
+            ignored_items: HashMap::new(),
+        };
+        assert_eq!(DropNames::bundled().validate(&filtered_items), []);
+
+        filtered_items.eqmt.insert(
+            "Octavia Prime".to_string(),
+            EquipmentItem {
+                item_type: EquipmentType::Warframes,
+                vaulted: false,
+                parts: HashMap::from([(
+                    "Octavia Prime Tail".to_string(),
+                    DucatItem {
+                        ducats: 45,
+                        count: 1,
+                    },
+                )]),
+            },
+        );
+        assert_eq!(
+            DropNames::bundled().validate(&filtered_items),
+            [UnresolvedItem {
+                name: "Octavia Prime Tail".to_string(),
+                equipment_type: EquipmentType::Warframes,
+                part_kind: Some("Tail".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn can_derive_drop_names_without_the_equipment_prefix() {
+        let json: serde_json::Value =
+            serde_json::from_slice(include_bytes!("../test-data/filtered_items.json")).unwrap();
+        let mut eqmt: HashMap<String, EquipmentItem> =
+            serde_json::from_value(json["eqmt"].clone()).unwrap();
+        let mut octavia = eqmt["Octavia Prime"].clone();
+        let systems = octavia.parts.remove("Octavia Prime Systems").unwrap();
+        octavia.parts.insert("Octavia Systems".to_string(), systems);
+        eqmt.insert("Octavia Prime".to_string(), octavia);
+        let filtered_items = FilteredItems {
+            errors: Vec::new(),
+            relics: Relics::default(),
+            eqmt,
+            ignored_items: HashMap::new(),
+        };
+        assert_eq!(DropNames::bundled().validate(&filtered_items), []);
+
+        assert_eq!(part_kind("Octavia Prime", "Octavia Systems"), None);
+        assert_eq!(
+            DropNames::bundled().drop_name("Octavia Systems", EquipmentType::Warframes, None),
+            Some("Octavia Systems Blueprint".to_string())
+        );
+        assert_eq!(
+            DropNames::bundled().drop_name(
+                "Octavia Systems Blueprint",
+                EquipmentType::Warframes,
+                None
+            ),
+            Some("Octavia Systems Blueprint".to_string())
+        );
+        // Only whole words count as the part
+        assert_eq!(
+            DropNames::bundled().drop_name("Octavia Tail", EquipmentType::Warframes, None),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        assert!(DropNames::from_json(r#"{"version": 2, "rules": []}"#).is_err());
+    }
+}
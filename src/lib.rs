@@ -1,7 +1,19 @@
+pub mod burst;
+pub mod capture;
+pub mod config;
 pub mod database;
+pub mod diff;
+pub mod drop_names;
+pub mod locale;
 pub mod ocr;
+pub mod preprocess;
+pub mod rewards;
+pub mod sets;
 pub mod statistics;
 pub mod testing;
 pub mod theme;
+pub mod theme_fit;
+pub mod theme_presets;
 pub mod utils;
+pub mod versioned;
 pub mod wfinfo_data;
@@ -0,0 +1,256 @@
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use log::warn;
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    database::Database,
+    versioned::{self, Versioned},
+};
+
+/// Game language codes along with the tesseract language used to read them
+const TESSERACT_LANGUAGES: [(&str, &str); 15] = [
+    ("en", "eng"),
+    ("de", "deu"),
+    ("es", "spa"),
+    ("fr", "fra"),
+    ("it", "ita"),
+    ("ja", "jpn"),
+    ("ko", "kor"),
+    ("pl", "pol"),
+    ("pt", "por"),
+    ("ru", "rus"),
+    ("tc", "chi_tra"),
+    ("th", "tha"),
+    ("tr", "tur"),
+    ("uk", "ukr"),
+    ("zh", "chi_sim"),
+];
+
+/// Translations shipped in `data/locales`, extended by the files in the locale directory
+const BUNDLED_LOCALES: [(&str, &str); 1] = [("de", include_str!("../data/locales/de.json"))];
+
+/// The game language rewards are read in
+#[derive(Clone, Debug, PartialEq)]
+pub struct Locale {
+    pub code: String,
+    pub tesseract_language: String,
+    /// Localised reward names mapped to the canonical item names
+    pub names: HashMap<String, String>,
+    /// Translations of the words of English reward names, for the names not listed in `names`
+    pub words: HashMap<String, String>,
+}
+
+/// Contents of `<locale>.json` in the locale directory
+#[derive(Clone, Debug, Deserialize)]
+struct LocaleFile {
+    tesseract_language: Option<String>,
+    #[serde(default)]
+    names: HashMap<String, String>,
+    #[serde(default)]
+    words: HashMap<String, String>,
+}
+
+impl Versioned for LocaleFile {
+    const VERSION: u32 = 1;
+    const KIND: &'static str = "locale file";
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale {
+            code: "en".to_string(),
+            tesseract_language: "eng".to_string(),
+            names: HashMap::new(),
+            words: HashMap::new(),
+        }
+    }
+}
+
+impl Locale {
+    /// `$XDG_CONFIG_HOME/wfinfo/locales`
+    pub fn directory() -> PathBuf {
+        Config::directory().join("locales")
+    }
+
+    /// Loads the bundled translations for `code` along with `<directory>/<code>.json`, whose
+    /// entries take precedence. Locales without either fall back to the canonical names.
+    pub fn load(code: &str, directory: &Path) -> Result<Locale, anyhow::Error> {
+        let bundled = BUNDLED_LOCALES
+            .iter()
+            .find(|(bundled_code, _)| *bundled_code == code)
+            .map(|(_, text)| {
+                versioned::from_json::<LocaleFile>(text).expect("Bundled locale is invalid")
+            });
+        let path = directory.join(format!("{code}.json"));
+        let file = match read_to_string(&path) {
+            Ok(text) => Some(
+                versioned::from_json::<LocaleFile>(&text)
+                    .with_context(|| format!("Failed to parse locale file {}", path.display()))?,
+            ),
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        let files: Vec<_> = bundled.into_iter().chain(file).collect();
+        let tesseract_language = match files
+            .iter()
+            .rev()
+            .find_map(|file| file.tesseract_language.clone())
+        {
+            Some(language) => language,
+            None => match TESSERACT_LANGUAGES.iter().find(|(game, _)| *game == code) {
+                Some((_, language)) => language.to_string(),
+                None => bail!(
+                    "Unknown locale {code}, add a tesseract_language to {}",
+                    path.display()
+                ),
+            },
+        };
+        if files.is_empty() && code != "en" {
+            warn!(
+                "No translations found at {}, only English names will be recognised",
+                path.display()
+            );
+        }
+
+        let mut locale = Locale {
+            code: code.to_owned(),
+            tesseract_language,
+            names: HashMap::new(),
+            words: HashMap::new(),
+        };
+        for file in files {
+            locale.names.extend(file.names);
+            locale.words.extend(file.words);
+        }
+        Ok(locale)
+    }
+}
+
+impl Database {
+    /// Sets the localised names of all items translated by `locale`, items it doesn't list get
+    /// their reward screen name translated word by word
+    pub fn with_locale(mut self, locale: &Locale) -> Database {
+        let items = self.items_mut();
+        for (localised, canonical) in locale.names.iter() {
+            match items
+                .iter_mut()
+                .find(|item| &item.name == canonical || &item.drop_name == canonical)
+            {
+                Some(item) => item.localised_name = Some(localised.to_owned()),
+                None => warn!("Translation {localised:?} refers to unknown item {canonical:?}"),
+            }
+        }
+        if !locale.words.is_empty() {
+            for item in items
+                .iter_mut()
+                .filter(|item| item.localised_name.is_none())
+            {
+                let translated = item
+                    .drop_name
+                    .split_whitespace()
+                    .map(|word| locale.words.get(word).map_or(word, String::as_str))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                if translated != item.drop_name {
+                    item.localised_name = Some(translated);
+                }
+            }
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use crate::ocr::normalize_string;
+
+    use super::*;
+
+    #[test]
+    fn can_load_locales() {
+        let temporary = TempDir::new().unwrap();
+        let directory = temporary.path();
+        fs::write(
+            directory.join("de.json"),
+            r#"{"version": 1, "names": {"Octavia Prime Systeme Blaupause": "Octavia Prime Systems"}}"#,
+        )
+        .unwrap();
+
+        let german = Locale::load("de", directory).unwrap();
+        assert_eq!(german.tesseract_language, "deu");
+        assert_eq!(german.names.len(), 1);
+        assert_eq!(german.words["Blueprint"], "Blaupause");
+
+        let russian = Locale::load("ru", directory).unwrap();
+        assert_eq!(russian.tesseract_language, "rus");
+        assert!(russian.names.is_empty());
+
+        assert_eq!(Locale::load("en", directory).unwrap(), Locale::default());
+        assert!(Locale::load("xx", directory).is_err());
+    }
+
+    #[test]
+    fn can_find_localised_items() {
+        let locale = Locale {
+            code: "ru".to_string(),
+            tesseract_language: "rus".to_string(),
+            names: HashMap::from([(
+                "Чертёж: Системы Октавии Прайм".to_string(),
+                "Octavia Prime Systems".to_string(),
+            )]),
+            words: HashMap::new(),
+        };
+        let db = Database::from_fixture().with_locale(&locale);
+
+        let item = db
+            .find_item(&normalize_string("Чертеж: Системы Октавии Праим"), None)
+            .unwrap();
+        assert_eq!(item.name, "Octavia Prime Systems");
+        let item = db
+            .find_item("OctaviaPrimeSystemsBlueprint", Some(0))
+            .unwrap();
+        assert_eq!(item.name, "Octavia Prime Systems");
+    }
+
+    #[test]
+    fn can_find_items_using_bundled_translations() {
+        let temporary = TempDir::new().unwrap();
+        let locale = Locale::load("de", temporary.path()).unwrap();
+        let db = Database::from_fixture().with_locale(&locale);
+
+        let item = db
+            .find_item(
+                &normalize_string("Octavia Prime Systeme Blaupause"),
+                Some(0),
+            )
+            .unwrap();
+        assert_eq!(item.name, "Octavia Prime Systems");
+        let item = db
+            .find_item(&normalize_string("Akjagara Prime Lauf"), Some(0))
+            .unwrap();
+        assert_eq!(item.name, "Akjagara Prime Barrel");
+        // English names are still recognised
+        let item = db
+            .find_item("OctaviaPrimeSystemsBlueprint", Some(0))
+            .unwrap();
+        assert_eq!(item.name, "Octavia Prime Systems");
+    }
+
+    #[test]
+    fn normalizes_unicode_letters() {
+        assert_eq!(normalize_string("Éclat de Riven\n"), "ÉclatdeRiven");
+        assert_eq!(normalize_string("Oﬃcial 2X"), "OfficialX");
+    }
+}
@@ -1,41 +1,142 @@
 use lazy_static::lazy_static;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use std::f32::consts::PI;
-use std::{collections::HashMap, sync::Mutex};
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
 use tesseract::Tesseract;
+use unicode_normalization::UnicodeNormalization;
 
+use anyhow::bail;
+use clap::ValueEnum;
 use image::{DynamicImage, GenericImageView, Pixel, Rgb};
-use log::debug;
+use levenshtein::levenshtein;
+use log::{debug, info, log_enabled, warn, Level};
+use serde::{Deserialize, Serialize};
 
-use crate::theme::Theme;
+use crate::{preprocess::Preprocessing, theme::Theme};
 
 const PIXEL_REWARD_WIDTH: f32 = 968.0;
 const PIXEL_REWARD_HEIGHT: f32 = 235.0;
 const PIXEL_REWARD_YDISPLAY: f32 = 316.0;
 const PIXEL_REWARD_LINE_HEIGHT: f32 = 48.0;
 
-pub fn detect_theme(image: &DynamicImage) -> Theme {
-    let screen_scaling = if image.width() * 9 > image.height() * 16 {
-        image.height() as f32 / 1080.0
-    } else {
-        image.width() as f32 / 1920.0
-    };
+/// A screenshot of the game, or the part of one the rewards are read from.
+///
+/// The rewards are located relative to the size of the whole screen, so a cropped frame
+/// keeps that size along with where on the screen it was cropped from.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub image: DynamicImage,
+    /// Left edge of the image on the screen
+    pub x: u32,
+    /// Top edge of the image on the screen
+    pub y: u32,
+    pub screen_width: u32,
+    pub screen_height: u32,
+}
+
+impl Frame {
+    /// A screenshot of the whole screen
+    pub fn new(image: DynamicImage) -> Frame {
+        Frame {
+            x: 0,
+            y: 0,
+            screen_width: image.width(),
+            screen_height: image.height(),
+            image,
+        }
+    }
+
+    /// Crops a screenshot to the [`reward_area`], so the full resolution screenshot can be dropped
+    pub fn reward_area(image: &DynamicImage) -> Frame {
+        let area = reward_area(image.width(), image.height());
+        Frame {
+            image: image.crop_imm(area.x, area.y, area.width, area.height),
+            x: area.x,
+            y: area.y,
+            screen_width: image.width(),
+            screen_height: image.height(),
+        }
+    }
+
+    fn scaling(&self) -> f32 {
+        screen_scaling(self.screen_width, self.screen_height)
+    }
+
+    /// Center of the screen relative to the image
+    fn center(&self) -> (f32, f32) {
+        (
+            self.screen_width as f32 / 2.0 - self.x as f32,
+            self.screen_height as f32 / 2.0 - self.y as f32,
+        )
+    }
+}
+
+impl From<DynamicImage> for Frame {
+    fn from(image: DynamicImage) -> Self {
+        Frame::new(image)
+    }
+}
+
+/// Part of a screen of the given size that the rewards are read from.
+///
+/// Covers the [`reward_search_area`] along with the status line above it, widened to the left
+/// to include the part of the columns [`rank_themes`] samples in those rows.
+pub fn reward_area(screen_width: u32, screen_height: u32) -> Rect {
+    let scaling = screen_scaling(screen_width, screen_height);
+    let center = (screen_width as f32 / 2.0, screen_height as f32 / 2.0);
+    let (most_left, most_top, most_width, most_bot) = search_bounds(scaling, center);
+    let status_height = PIXEL_REWARD_LINE_HEIGHT / 2.0 * scaling;
+    let left = (most_left.min(most_width / 4.0).max(0.0) as u32).min(screen_width);
+    let top = ((most_top - status_height).max(0.0) as u32).min(screen_height);
+    let right = ((most_left + most_width).ceil().max(0.0) as u32).clamp(left, screen_width);
+    let bottom = (most_bot.ceil().max(0.0) as u32).clamp(top, screen_height);
+    Rect {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    }
+}
+
+/// The best matching theme, `None` if the frame contains none of the pixels sampled for it
+pub fn detect_theme(frame: &Frame) -> Option<Theme> {
+    rank_themes(frame)
+        .into_iter()
+        .next()
+        .map(|(theme, _)| theme)
+}
+
+/// Every theme found on the reward screen along with its share of the matching pixels, best first.
+///
+/// Samples columns at the left of the reward screen, widening towards the bottom of the screen.
+/// Cropped frames only sample the part of those columns they contain.
+pub fn rank_themes(frame: &Frame) -> Vec<(Theme, f32)> {
+    let image = &frame.image;
+    let screen_scaling = frame.scaling();
 
     let line_height = PIXEL_REWARD_LINE_HEIGHT / 2.0 * screen_scaling;
     let most_width = PIXEL_REWARD_WIDTH * screen_scaling;
 
     let min_width = most_width / 4.0;
+    let candidates: Vec<_> = Theme::iter().collect();
 
-    let weights = (line_height as u32..image.height())
+    let screen_height = frame.screen_height as f32;
+    let rows = (line_height as u32).max(frame.y)..frame.screen_height.min(frame.y + image.height());
+    let weights = rows
         .into_par_iter()
         .fold(HashMap::new, |mut weights: HashMap<Theme, f32>, y| {
-            let perc = (y as f32 - line_height) / (image.height() as f32 - line_height);
+            let perc = (y as f32 - line_height) / (screen_height - line_height);
             let total_width = min_width * perc + min_width;
-            for x in 0..total_width as u32 {
-                let closest = Theme::closest_from_color(
-                    image
-                        .get_pixel(x + (most_width - total_width) as u32 / 2, y)
-                        .to_rgb(),
+            let left = (most_width - total_width) as u32 / 2;
+            let columns =
+                left.max(frame.x)..(left + total_width as u32).min(frame.x + image.width());
+            for x in columns {
+                let closest = Theme::closest_from_color_among(
+                    image.get_pixel(x - frame.x, y - frame.y).to_rgb(),
+                    &candidates,
                 );
 
                 *weights.entry(closest.0).or_insert(0.0) += 1.0 / (1.0 + closest.1).powi(4)
@@ -49,52 +150,104 @@ pub fn detect_theme(image: &DynamicImage) -> Theme {
             a
         });
 
-    debug!("{:#?}", weights);
+    let total: f32 = weights.values().sum();
+    let mut ranking: Vec<_> = weights
+        .into_iter()
+        .map(|(theme, weight)| (theme, weight / total))
+        .collect();
+    ranking.sort_by(|a, b| b.1.total_cmp(&a.1));
+    debug!("{:#?}", ranking);
+    ranking
+}
 
-    weights
-        .iter()
-        .max_by(|a, b| a.1.total_cmp(b.1))
-        .unwrap()
-        .0
-        .to_owned()
+/// Difference between the scores of the best and the second best theme
+pub fn theme_margin(ranking: &[(Theme, f32)]) -> f32 {
+    match ranking {
+        [] => 0.0,
+        [(_, best)] => *best,
+        [(_, best), (_, second), ..] => best - second,
+    }
 }
 
-pub fn extract_parts(image: &DynamicImage, theme: Theme) -> Vec<DynamicImage> {
-    image.save("input.png").unwrap();
-    let screen_scaling = if image.width() * 9 > image.height() * 16 {
-        image.height() as f32 / 1080.0
-    } else {
-        image.width() as f32 / 1920.0
-    };
-    let line_height = (PIXEL_REWARD_LINE_HEIGHT / 2.0 * screen_scaling) as usize;
+pub fn extract_parts(frame: &Frame, theme: Theme) -> Vec<DynamicImage> {
+    let part_box = locate_part_box(frame, &theme);
+    filter_and_separate_parts_from_part_box(
+        frame
+            .image
+            .crop_imm(part_box.x, part_box.y, part_box.width, part_box.height),
+        theme,
+    )
+}
 
-    let width = image.width() as f32;
-    let height = image.height() as f32;
+/// Left, top, width and bottom of the area searched for the reward names at the largest UI scale,
+/// relative to the given screen center
+fn search_bounds(screen_scaling: f32, (center_x, center_y): (f32, f32)) -> (f32, f32, f32, f32) {
     let most_width = PIXEL_REWARD_WIDTH * screen_scaling;
-    let most_left = width / 2.0 - most_width / 2.0;
+    let most_left = center_x - most_width / 2.0;
     // Most Top = pixleRewardYDisplay - pixleRewardHeight + pixelRewardLineHeight
     //                   (316          -        235        +       44)    *    1.1    =    137
-    let most_top = height / 2.0
+    let most_top = center_y
         - ((PIXEL_REWARD_YDISPLAY - PIXEL_REWARD_HEIGHT + PIXEL_REWARD_LINE_HEIGHT)
             * screen_scaling);
     let most_bot =
-        height / 2.0 - ((PIXEL_REWARD_YDISPLAY - PIXEL_REWARD_HEIGHT) * screen_scaling * 0.5);
+        center_y - ((PIXEL_REWARD_YDISPLAY - PIXEL_REWARD_HEIGHT) * screen_scaling * 0.5);
+    (most_left, most_top, most_width, most_bot)
+}
+
+/// Area searched for the reward names by [`locate_part_box`], also called the prefilter
+pub fn reward_search_area(frame: &Frame) -> Rect {
+    let (most_left, most_top, most_width, most_bot) =
+        search_bounds(frame.scaling(), frame.center());
+    Rect {
+        x: most_left as u32,
+        y: most_top as u32,
+        width: most_width as u32,
+        height: (most_bot - most_top) as u32,
+    }
+}
+
+/// Where [`locate_part_box`] found the reward names and at which UI scale
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PartBoxFit {
+    /// See [`reward_search_area`]
+    pub search_area: Rect,
+    /// UI scale of the best fitting text rows, between 0.5 and 1.0
+    pub scale: f32,
+    pub part_box: Rect,
+}
+
+/// Finds the area containing all reward names by fitting the expected text rows at different UI scales
+pub fn locate_part_box(frame: &Frame, theme: &Theme) -> Rect {
+    if log_enabled!(Level::Trace) {
+        if let Err(err) = frame.image.save("input.png") {
+            warn!("Failed to save input.png: {err}");
+        }
+    }
+    fit_part_box(frame, theme).part_box
+}
 
-    let prefilter = image.crop_imm(
-        most_left as u32,
-        most_top as u32,
-        most_width as u32,
-        (most_bot - most_top) as u32,
+/// Like [`locate_part_box`], but also returns the search area and the chosen UI scale
+pub fn fit_part_box(frame: &Frame, theme: &Theme) -> PartBoxFit {
+    let screen_scaling = frame.scaling();
+    let line_height = (PIXEL_REWARD_LINE_HEIGHT / 2.0 * screen_scaling) as usize;
+
+    let (_, center_y) = frame.center();
+    let (most_left, most_top, _, _) = search_bounds(screen_scaling, frame.center());
+    let search_area = reward_search_area(frame);
+    let prefilter = frame.image.crop_imm(
+        search_area.x,
+        search_area.y,
+        search_area.width,
+        search_area.height,
     );
-    let mut prefilter_draw = prefilter.clone().into_rgb8();
-    // prefilter.save("prefilter.png").unwrap();
 
+    let filter = theme.resolved();
     let mut rows = Vec::<usize>::new();
     for y in 0..prefilter.height() {
         let mut count = 0;
         for x in 0..prefilter.width() {
             let color = prefilter.get_pixel(x, y).to_rgb();
-            if theme.threshold_filter(color) {
+            if filter.matches(color) {
                 count += 1;
             }
         }
@@ -129,11 +282,6 @@ pub fn extract_parts(image: &DynamicImage, theme: Theme) -> Vec<DynamicImage> {
         let mut w = 0.0;
         for loc in text_top..text_top_bot + 1 {
             w += (scale_width as f32 * 0.06 - rows[y_from_top + loc] as f32).abs();
-            prefilter_draw.put_pixel(
-                prefilter_draw.width() / 2 + i as u32,
-                (y_from_top + loc) as u32,
-                Rgb([255; 3]),
-            );
         }
         top_weights.push(w);
 
@@ -144,22 +292,12 @@ pub fn extract_parts(image: &DynamicImage, theme: Theme) -> Vec<DynamicImage> {
             } else {
                 w += (scale_width as f32 * 0.24 - rows[y_from_top + loc] as f32).abs();
             }
-            prefilter_draw.put_pixel(
-                prefilter_draw.width() / 2 + i as u32,
-                (y_from_top + loc) as u32,
-                Rgb([0, 255, 0]),
-            );
         }
         mid_weights.push(w);
 
         let mut w = 0.0;
         for loc in text_both_bot..text_tail_bot {
             w += 10.0 * (scale_width as f32 * 0.007 - rows[y_from_top + loc] as f32).abs();
-            prefilter_draw.put_pixel(
-                prefilter_draw.width() / 2 + i as u32,
-                (y_from_top + loc) as u32,
-                Rgb([0, 0, 255]),
-            );
         }
         bot_weights.push(w);
 
@@ -212,73 +350,64 @@ pub fn extract_parts(image: &DynamicImage, theme: Theme) -> Vec<DynamicImage> {
 
     let crop_width = PIXEL_REWARD_WIDTH * screen_scaling * high_scaling;
     let crop_left = prefilter.width() as f32 / 2.0 - crop_width / 2.0;
-    let crop_top = height / 2.0
+    let crop_top = center_y
         - (PIXEL_REWARD_YDISPLAY - PIXEL_REWARD_HEIGHT + PIXEL_REWARD_LINE_HEIGHT)
             * screen_scaling
             * high_scaling;
     let crop_bot =
-        height / 2.0 - (PIXEL_REWARD_YDISPLAY - PIXEL_REWARD_HEIGHT) * screen_scaling * low_scaling;
+        center_y - (PIXEL_REWARD_YDISPLAY - PIXEL_REWARD_HEIGHT) * screen_scaling * low_scaling;
     let crop_hei = crop_bot - crop_top;
     let crop_top = crop_top - most_top;
 
-    let partial_screenshot = DynamicImage::ImageRgb8(prefilter.into_rgb8()).crop_imm(
-        crop_left as u32,
-        crop_top as u32,
-        crop_width as u32,
-        crop_hei as u32,
-    );
+    // Clamped to the prefilter area the same way cropping it would
+    let partial_left = (crop_left as u32).min(prefilter.width());
+    let partial_top = (crop_top as u32).min(prefilter.height());
+    let partial_screenshot = Rect {
+        x: most_left as u32 + partial_left,
+        y: most_top as u32 + partial_top,
+        width: (crop_width as u32).min(prefilter.width() - partial_left),
+        height: (crop_hei as u32).min(prefilter.height() - partial_top),
+    };
 
-    // Draw top 5
-    for (i, y) in top_five.iter().enumerate() {
-        for x in 0..prefilter_draw.width() {
-            prefilter_draw.put_pixel(x, *y as u32, Rgb([255 - i as u8 * 50, 0, 0]));
-        }
-    }
-    // Draw histogram
-    for (y, row) in rows.iter().enumerate() {
-        for x in 0..*row {
-            prefilter_draw.put_pixel(x as u32, y as u32, Rgb([0, 255, 0]));
-        }
+    PartBoxFit {
+        search_area,
+        scale: scaling,
+        part_box: partial_screenshot,
     }
-
-    // prefilter_draw.save("prefilter.png").unwrap();
-
-    // partial_screenshot.save("partial_screenshot.png").unwrap();
-
-    filter_and_separate_parts_from_part_box(partial_screenshot, theme)
 }
 
 pub fn filter_and_separate_parts_from_part_box(
     image: DynamicImage,
     theme: Theme,
 ) -> Vec<DynamicImage> {
-    let mut filtered = image.into_rgb8();
+    let filtered = threshold_image(&image, &theme);
+    separate_parts_from_part_box(&image, &theme)
+        .iter()
+        .map(|rect| filtered.crop_imm(rect.x, rect.y, rect.width, rect.height))
+        .collect()
+}
+
+/// Splits the area containing all reward names into three or four slots,
+/// depending on which fits the theme coloured columns better
+pub fn separate_parts_from_part_box(image: &DynamicImage, theme: &Theme) -> Vec<Rect> {
+    let image = image.to_rgb8();
+    let filter = theme.resolved();
 
     let mut _weight = 0.0;
     let mut total_even = 0.0;
     let mut total_odd = 0.0;
-    for x in 0..filtered.width() {
+    for x in 0..image.width() {
         let mut count = 0;
-        for y in 0..filtered.height() {
-            let pixel = filtered.get_pixel_mut(x, y);
-            if theme.threshold_filter(*pixel) {
-                *pixel = Rgb([0; 3]);
+        for y in 0..image.height() {
+            if filter.matches(*image.get_pixel(x, y)) {
                 count += 1;
-            } else {
-                *pixel = Rgb([255; 3]);
             }
         }
 
-        count = count.min(filtered.height() / 3);
-        let cosine = (8.0 * x as f32 * PI / filtered.width() as f32).cos();
+        count = count.min(image.height() / 3);
+        let cosine = (8.0 * x as f32 * PI / image.width() as f32).cos();
         let cosine_thing = cosine.powi(3);
 
-        // filtered.put_pixel(
-        //     x,
-        //     ((cosine_thing / 2.0 + 0.5) * (filtered.height() - 1) as f32) as u32,
-        //     Rgb([255, 0, 0]),
-        // );
-
         // debug!("{}", cosine_thing);
 
         let this_weight = cosine_thing * count as f32;
@@ -291,10 +420,6 @@ pub fn filter_and_separate_parts_from_part_box(
         }
     }
 
-    // filtered
-    //     .save("filtered.png")
-    //     .expect("Failed to write filtered image");
-
     if total_even == 0.0 && total_odd == 0.0 {
         return vec![];
     }
@@ -303,8 +428,8 @@ pub fn filter_and_separate_parts_from_part_box(
     // debug!("Even: {}", total_even / total);
     // debug!("Odd: {}", total_odd / total);
 
-    let box_width = filtered.width() / 4;
-    let box_height = filtered.height();
+    let box_width = image.width() / 4;
+    let box_height = image.height();
 
     let mut curr_left = 0;
     let mut player_count = 4;
@@ -314,22 +439,342 @@ pub fn filter_and_separate_parts_from_part_box(
         player_count = 3;
     }
 
-    let mut images = Vec::new();
+    (0..player_count)
+        .map(|i| Rect {
+            x: curr_left + i * box_width,
+            y: 0,
+            width: box_width,
+            height: box_height,
+        })
+        .collect()
+}
+
+/// How the reward name boxes are found in a screenshot
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Localiser {
+    /// Fits the expected text row densities at 50 scale candidates, see [`extract_parts`]
+    #[default]
+    Heuristic,
+    /// Finds the name row and the slots from theme coloured pixels, see [`locate_reward_boxes`]
+    Template,
+}
+
+/// Settings for turning a screenshot into reward names
+#[derive(Clone, Debug)]
+pub struct OcrOptions {
+    pub localiser: Localiser,
+    pub preprocessing: Preprocessing,
+    /// Reads every line of a reward name separately, see [`split_lines`]
+    pub split_lines: bool,
+    /// Reads the owned count and crafted checkmark above each reward name, see [`status_region`]
+    pub read_status: bool,
+    /// Detected themes winning by a smaller score than this are double checked with the runner-up
+    pub theme_margin: f32,
+}
 
-    let dynamic_image = DynamicImage::ImageRgb8(filtered);
-    for i in 0..player_count {
-        let cropped = dynamic_image.crop_imm(curr_left + i * box_width, 0, box_width, box_height);
-        // cropped
-        //     .save(format!("part-{}.png", i))
-        //     .expect("Failed to write image");
-        images.push(cropped);
+impl Default for OcrOptions {
+    fn default() -> Self {
+        OcrOptions {
+            localiser: Localiser::default(),
+            preprocessing: Preprocessing::default(),
+            split_lines: false,
+            read_status: false,
+            theme_margin: 0.1,
+        }
     }
+}
 
-    images
+/// Area of an image in pixels
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
 }
 
+fn screen_scaling(width: u32, height: u32) -> f32 {
+    if width * 9 > height * 16 {
+        height as f32 / 1080.0
+    } else {
+        width as f32 / 1920.0
+    }
+}
+
+/// Groups the indices for which `keep` holds into inclusive ranges, bridging gaps up to `max_gap`
+fn group_runs(len: usize, max_gap: usize, keep: impl Fn(usize) -> bool) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for index in (0..len).filter(|&index| keep(index)) {
+        match runs.last_mut() {
+            Some(run) if index - run.1 <= max_gap + 1 => run.1 = index,
+            _ => runs.push((index, index)),
+        }
+    }
+    runs
+}
+
+/// Finds the bounding box of every reward name on the reward screen.
+///
+/// Searches the same area as [`extract_parts`] for theme coloured pixels. Rows containing long
+/// horizontal lines are box borders and ignored, the lowest remaining band of text holds the
+/// reward names. Vertical lines taller than a line of text are separators between the reward
+/// boxes and give the slot width, otherwise the spacing between the names is used.
+/// Returns `None` if no names or more than four slots are found.
+pub fn locate_reward_boxes(frame: &Frame, theme: &Theme) -> Option<Vec<Rect>> {
+    let image = &frame.image;
+    let screen_scaling = frame.scaling();
+    let line_height = PIXEL_REWARD_LINE_HEIGHT / 2.0 * screen_scaling;
+
+    let (center_x, center_y) = frame.center();
+    let search_width = (PIXEL_REWARD_WIDTH * screen_scaling).min(frame.screen_width as f32);
+    let search_left = (center_x - search_width / 2.0).max(0.0);
+    let search_top = (center_y
+        - (PIXEL_REWARD_YDISPLAY - PIXEL_REWARD_HEIGHT + PIXEL_REWARD_LINE_HEIGHT)
+            * screen_scaling)
+        .max(0.0);
+    let search_bottom =
+        center_y - (PIXEL_REWARD_YDISPLAY - PIXEL_REWARD_HEIGHT) * screen_scaling * 0.5;
+    let region = image.crop_imm(
+        search_left as u32,
+        search_top as u32,
+        search_width as u32,
+        (search_bottom - search_top) as u32,
+    );
+    let (width, height) = (region.width() as usize, region.height() as usize);
+    let filter = theme.resolved();
+    let mask: Vec<bool> = region
+        .to_rgb8()
+        .pixels()
+        .map(|pixel| filter.matches(*pixel))
+        .collect();
+    let is_set = |x: usize, y: usize| mask[y * width + x];
+
+    let is_border = |y: usize| {
+        let mut longest = 0;
+        let mut current = 0;
+        for x in 0..width {
+            current = if is_set(x, y) { current + 1 } else { 0 };
+            longest = longest.max(current);
+        }
+        longest >= width / 8
+    };
+    let min_row_count = (width / 200).max(2);
+    let bands = group_runs(height, (line_height / 2.0) as usize, |y| {
+        (0..width).filter(|&x| is_set(x, y)).count() >= min_row_count && !is_border(y)
+    });
+    let (band_top, band_bottom) = bands
+        .into_iter()
+        .rev()
+        .find(|(top, bottom)| (bottom - top + 1) as f32 >= line_height * 0.3)?;
+
+    let columns: Vec<usize> = (0..width)
+        .map(|x| (band_top..=band_bottom).filter(|&y| is_set(x, y)).count())
+        .collect();
+    // Text is at most a line high, so anything taller is a line
+    let separator_height = (line_height * 1.5) as usize;
+    let vertical_runs: Vec<usize> = (0..width)
+        .map(|x| {
+            let mut longest = 0;
+            let mut current = 0;
+            for y in 0..height {
+                current = if is_set(x, y) { current + 1 } else { 0 };
+                longest = longest.max(current);
+            }
+            longest
+        })
+        .collect();
+    let is_separator = |x: usize| vertical_runs[x] >= separator_height;
+    let separators: Vec<f32> = group_runs(width, 1, is_separator)
+        .into_iter()
+        .map(|(start, end)| (start + end) as f32 / 2.0)
+        .collect();
+    let names: Vec<f32> = group_runs(width, line_height as usize, |x| {
+        columns[x] > 0 && !is_separator(x)
+    })
+    .into_iter()
+    .map(|(start, end)| (start + end) as f32 / 2.0)
+    .collect();
+    debug!("Separators: {separators:?}, names: {names:?}");
+
+    let spacing = |centers: &[f32]| {
+        centers
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .min_by(|a, b| a.total_cmp(b))
+    };
+    let slot_width = spacing(&separators)
+        .or_else(|| spacing(&names))
+        .unwrap_or(width as f32 / 4.0);
+    let first = *names.first()?;
+    let last = *names.last()?;
+    let slot_count = ((last - first) / slot_width).round() as usize + 1;
+    if slot_count > 4 {
+        return None;
+    }
+
+    let padding = line_height / 4.0;
+    let top = (search_top + band_top as f32 - padding).max(0.0);
+    let bottom = (search_top + band_bottom as f32 + padding).min(image.height() as f32);
+    let boxes = (0..slot_count)
+        .map(|slot| {
+            let left = (search_left + first + (slot as f32 - 0.5) * slot_width).max(0.0);
+            let right = (left + slot_width).min(image.width() as f32);
+            Rect {
+                x: left as u32,
+                y: top as u32,
+                width: (right - left) as u32,
+                height: (bottom - top) as u32,
+            }
+        })
+        .collect();
+    Some(boxes)
+}
+
+/// Black text on a white background, like the parts returned by [`extract_parts`]
+pub fn threshold_image(image: &DynamicImage, theme: &Theme) -> DynamicImage {
+    let mut filtered = image.to_rgb8();
+    let filter = theme.resolved();
+    for pixel in filtered.pixels_mut() {
+        *pixel = if filter.matches(*pixel) {
+            Rgb([0; 3])
+        } else {
+            Rgb([255; 3])
+        };
+    }
+    DynamicImage::ImageRgb8(filtered)
+}
+
+/// Extracts the reward name images using the given localiser,
+/// the template localiser falls back to the heuristic if it finds no names
+pub fn extract_parts_with(frame: &Frame, theme: Theme, localiser: Localiser) -> Vec<DynamicImage> {
+    extract_raw_parts(frame, &theme, localiser)
+        .iter()
+        .map(|part| threshold_image(part, &theme))
+        .collect()
+}
+
+/// Like [`extract_parts_with`], but returns the unfiltered reward name images
+pub fn extract_raw_parts(frame: &Frame, theme: &Theme, localiser: Localiser) -> Vec<DynamicImage> {
+    locate_parts(frame, theme, localiser)
+        .iter()
+        .map(|rect| {
+            frame
+                .image
+                .crop_imm(rect.x, rect.y, rect.width, rect.height)
+        })
+        .collect()
+}
+
+/// Finds the reward name boxes using the given localiser
+pub fn locate_parts(frame: &Frame, theme: &Theme, localiser: Localiser) -> Vec<Rect> {
+    locate_parts_with_fit(frame, theme, localiser).0
+}
+
+/// Like [`locate_parts`], also returning the part box fit if the heuristic was used
+fn locate_parts_with_fit(
+    frame: &Frame,
+    theme: &Theme,
+    localiser: Localiser,
+) -> (Vec<Rect>, Option<PartBoxFit>) {
+    let boxes = match localiser {
+        Localiser::Template => {
+            let boxes = locate_reward_boxes(frame, theme);
+            if boxes.is_none() {
+                debug!("No reward boxes found, falling back to heuristic");
+            }
+            boxes
+        }
+        Localiser::Heuristic => None,
+    };
+    let (boxes, fit) = match boxes {
+        Some(boxes) => (boxes, None),
+        None => {
+            let fit = fit_part_box(frame, theme);
+            let part_box = fit.part_box;
+            let part_box_image =
+                frame
+                    .image
+                    .crop_imm(part_box.x, part_box.y, part_box.width, part_box.height);
+            let boxes = separate_parts_from_part_box(&part_box_image, theme)
+                .into_iter()
+                .map(|rect| Rect {
+                    x: part_box.x + rect.x,
+                    y: part_box.y + rect.y,
+                    ..rect
+                })
+                .collect();
+            (boxes, Some(fit))
+        }
+    };
+    debug!("Reward boxes: {boxes:?}");
+    (boxes, fit)
+}
+
+/// The line above a reward name showing how many of the item are owned, e.g. "✓ 2 Owned"
+pub fn status_region(frame: &Frame, name_box: &Rect) -> Option<Rect> {
+    let height = (PIXEL_REWARD_LINE_HEIGHT / 2.0 * frame.scaling()) as u32;
+    Some(Rect {
+        y: name_box.y.checked_sub(height)?,
+        height,
+        ..*name_box
+    })
+}
+
+/// Ownership shown on a reward slot
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SlotStatus {
+    /// Number of the item owned, `None` if the slot shows no count
+    pub owned: Option<usize>,
+    /// Whether the checkmark for an already crafted item is shown
+    pub crafted: bool,
+}
+
+impl SlotStatus {
+    /// Parses the status line OCR text, tolerating the usual misreadings like "0wned"
+    pub fn parse(text: &str) -> SlotStatus {
+        let words: Vec<_> = text.split_whitespace().collect();
+        let owned_index = words.iter().position(|word| {
+            let word: String = word
+                .to_lowercase()
+                .chars()
+                .map(|c| if c == '0' { 'o' } else { c })
+                .filter(|c| c.is_alphabetic())
+                .collect();
+            levenshtein(&word, "owned") <= 1
+        });
+        let owned = owned_index
+            .and_then(|index| index.checked_sub(1))
+            .and_then(|index| {
+                words[index]
+                    .chars()
+                    .filter(|c| c.is_ascii_digit())
+                    .collect::<String>()
+                    .parse()
+                    .ok()
+            });
+        // Tesseract reads the checkmark as one of these or as a lone "v"
+        let crafted = text.contains(['✓', '✔', '√', '☑'])
+            || owned_index.is_some_and(|index| {
+                words[..index.saturating_sub(1)]
+                    .iter()
+                    .any(|word| word.eq_ignore_ascii_case("v"))
+            });
+
+        SlotStatus { owned, crafted }
+    }
+}
+
+/// Text read from a reward slot
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DetectedReward {
+    pub name: String,
+    pub status: SlotStatus,
+}
+
+/// Keeps only the letters of `string`, expanding compatibility characters like ligatures first
 pub fn normalize_string(string: &str) -> String {
-    string.replace(|c: char| !c.is_ascii_alphabetic(), "")
+    string.nfkc().filter(|c| c.is_alphabetic()).collect()
 }
 
 pub fn image_to_string(tesseract: &mut Option<Tesseract>, image: &DynamicImage) -> String {
@@ -351,19 +796,398 @@ pub fn image_to_string(tesseract: &mut Option<Tesseract>, image: &DynamicImage)
     result
 }
 
+/// The tesseract language, fixed once [`OCR`] is first used
+static OCR_LANGUAGE: OnceLock<String> = OnceLock::new();
+
 lazy_static! {
-    pub static ref OCR: Mutex<Option<Tesseract>> = Mutex::new(Some(
-        Tesseract::new(None, Some("eng")).expect("Could not initialize Tesseract")
-    ));
+    pub static ref OCR: Mutex<Option<Tesseract>> = {
+        let language = OCR_LANGUAGE.get_or_init(|| "eng".to_string());
+        Mutex::new(Some(Tesseract::new(None, Some(language)).unwrap_or_else(
+            |_| panic!("Could not initialize Tesseract with language {language}"),
+        )))
+    };
+}
+
+/// Selects the tesseract language, e.g. "deu", has to be called before [`OCR`] is first used
+pub fn set_ocr_language(language: &str) -> Result<(), anyhow::Error> {
+    let selected = OCR_LANGUAGE.get_or_init(|| language.to_owned());
+    if selected != language {
+        bail!("The OCR language can't be changed to {language}, {selected} is already in use");
+    }
+    Ok(())
 }
 
-pub fn reward_image_to_reward_names(image: DynamicImage, theme: Option<Theme>) -> Vec<String> {
-    let theme = theme.unwrap_or_else(|| detect_theme(&image));
-    let parts = extract_parts(&image, theme);
+pub fn reward_image_to_reward_names(frame: impl Into<Frame>, theme: Option<Theme>) -> Vec<String> {
+    reward_image_to_reward_names_with(frame, theme, &OcrOptions::default())
+}
+
+pub fn reward_image_to_reward_names_with(
+    frame: impl Into<Frame>,
+    theme: Option<Theme>,
+    options: &OcrOptions,
+) -> Vec<String> {
+    detect_rewards(frame, theme, options)
+        .into_iter()
+        .map(|reward| reward.name)
+        .collect()
+}
+
+/// Reads the name of every reward, along with its owned count if [`OcrOptions::read_status`] is set.
+///
+/// Without a given theme, the best matching theme is used. If it reads no names or
+/// wins by less than [`OcrOptions::theme_margin`], the runner-up is tried as well and
+/// whichever reads more names is used.
+pub fn detect_rewards(
+    frame: impl Into<Frame>,
+    theme: Option<Theme>,
+    options: &OcrOptions,
+) -> Vec<DetectedReward> {
+    let frame = frame.into();
+    if let Some(theme) = theme {
+        return detect_rewards_with_theme(&frame, &theme, options);
+    }
+
+    let ranking = rank_themes(&frame);
+    let Some((best_theme, _)) = ranking.first() else {
+        warn!(
+            "No theme found in a {}x{} frame",
+            frame.image.width(),
+            frame.image.height()
+        );
+        return Vec::new();
+    };
+    let margin = theme_margin(&ranking);
+    let best = detect_rewards_with_theme(&frame, best_theme, options);
+    let best_count = readable_names(&best);
+    let runner_up = match ranking.get(1) {
+        Some((theme, _)) if best_count == 0 || margin < options.theme_margin => theme,
+        _ => return best,
+    };
+
+    debug!(
+        "Theme {best_theme:?} read {best_count} names with a margin of {margin}, trying {runner_up:?}"
+    );
+    let second = detect_rewards_with_theme(&frame, runner_up, options);
+    if readable_names(&second) > best_count {
+        info!("Using runner-up theme {runner_up:?}");
+        second
+    } else {
+        best
+    }
+}
+
+/// Names with at least a few letters, anything shorter is noise
+fn readable_names(rewards: &[DetectedReward]) -> usize {
+    rewards
+        .iter()
+        .filter(|reward| normalize_string(&reward.name).chars().count() >= 4)
+        .count()
+}
+
+fn detect_rewards_with_theme(
+    frame: &Frame,
+    theme: &Theme,
+    options: &OcrOptions,
+) -> Vec<DetectedReward> {
+    let boxes = locate_parts(frame, theme, options.localiser);
     debug!("Extracted part images");
+    read_slots(frame, theme, &boxes, options)
+}
+
+/// Intermediate results of reading the rewards using one theme, for inspecting the pipeline
+#[derive(Clone, Debug)]
+pub struct RewardTrace {
+    pub theme: Theme,
+    /// How the heuristic localiser found the reward names, `None` if the template localiser did
+    pub part_box: Option<PartBoxFit>,
+    /// Reward name box of every slot
+    pub slots: Vec<Rect>,
+    pub rewards: Vec<DetectedReward>,
+}
+
+/// Reads the rewards like [`detect_rewards`] does with a given theme, keeping every step's result
+pub fn trace_rewards(frame: &Frame, theme: &Theme, options: &OcrOptions) -> RewardTrace {
+    let (slots, part_box) = locate_parts_with_fit(frame, theme, options.localiser);
+    let rewards = read_slots(frame, theme, &slots, options);
+    RewardTrace {
+        theme: theme.clone(),
+        part_box,
+        slots,
+        rewards,
+    }
+}
+
+fn read_slots(
+    frame: &Frame,
+    theme: &Theme,
+    boxes: &[Rect],
+    options: &OcrOptions,
+) -> Vec<DetectedReward> {
+    boxes
+        .iter()
+        .map(|rect| {
+            let part = options.preprocessing.apply(
+                &frame
+                    .image
+                    .crop_imm(rect.x, rect.y, rect.width, rect.height),
+                theme,
+            );
+            let name = if options.split_lines {
+                split_lines(&part)
+                    .iter()
+                    .map(|line| image_to_string(&mut OCR.lock().unwrap(), line))
+                    .map(|line| line.trim().to_owned())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            } else {
+                image_to_string(&mut OCR.lock().unwrap(), &part)
+            };
+
+            let status = status_region(frame, rect)
+                .filter(|_| options.read_status)
+                .map(|region| {
+                    let status = options.preprocessing.apply(
+                        &frame
+                            .image
+                            .crop_imm(region.x, region.y, region.width, region.height),
+                        theme,
+                    );
+                    SlotStatus::parse(&image_to_string(&mut OCR.lock().unwrap(), &status))
+                })
+                .unwrap_or_default();
+
+            DetectedReward { name, status }
+        })
+        .collect()
+}
+
+/// Splits black text on white into one image per line of text using the rows containing text.
+/// Rows much thinner than the tallest line are considered noise.
+pub fn split_lines(image: &DynamicImage) -> Vec<DynamicImage> {
+    let luma = image.to_luma8();
+    let lines = group_runs(luma.height() as usize, 1, |y| {
+        (0..luma.width()).any(|x| luma.get_pixel(x, y as u32).0[0] < 128)
+    });
+    let tallest = lines
+        .iter()
+        .map(|(top, bottom)| bottom - top + 1)
+        .max()
+        .unwrap_or(0);
+    let lines: Vec<_> = lines
+        .into_iter()
+        .filter(|(top, bottom)| (bottom - top + 1) * 4 >= tallest)
+        .collect();
+    if lines.len() <= 1 {
+        return vec![image.clone()];
+    }
 
-    parts
+    lines
         .iter()
-        .map(|image| image_to_string(&mut OCR.lock().unwrap(), image))
+        .map(|&(top, bottom)| {
+            let margin = ((bottom - top + 1) / 4).max(2);
+            let top = top.saturating_sub(margin);
+            let bottom = (bottom + margin).min(luma.height() as usize - 1);
+            image.crop_imm(0, top as u32, image.width(), (bottom - top + 1) as u32)
+        })
         .collect()
 }
+
+#[cfg(test)]
+mod test {
+    use image::RgbImage;
+
+    use super::*;
+
+    /// A 1080p reward screen with a name in each of the given slot offsets
+    fn reward_screen(slots: &[f32], separators: bool) -> Frame {
+        let color = Rgb([190, 169, 102]);
+        let mut image = RgbImage::new(1920, 1080);
+        for slot in slots {
+            let center = 960.0 + slot * 242.0;
+            // Two words per name
+            for (start, end) in [(-70.0, -10.0), (0.0, 70.0)] {
+                for x in (center + start) as u32..(center + end) as u32 {
+                    for y in 460..480 {
+                        image.put_pixel(x, y, color);
+                    }
+                }
+            }
+        }
+        if separators {
+            for x in [718, 960, 1202] {
+                for y in 420..492 {
+                    image.put_pixel(x, y, color);
+                }
+            }
+        }
+        // Bottom border of the reward boxes
+        for x in 476..1444 {
+            for y in 490..492 {
+                image.put_pixel(x, y, color);
+            }
+        }
+        Frame::new(DynamicImage::ImageRgb8(image))
+    }
+
+    #[test]
+    fn can_rank_themes() {
+        let mut image = RgbImage::from_pixel(960, 540, Rgb([190, 169, 102]));
+        for y in 270..540 {
+            for x in 0..960 {
+                image.put_pixel(x, y, Rgb([36, 184, 242]));
+            }
+        }
+
+        let ranking = rank_themes(&Frame::new(DynamicImage::ImageRgb8(image)));
+        assert_eq!(ranking[0].0, Theme::Lotus);
+        assert_eq!(ranking[1].0, Theme::Vitruvian);
+        assert!(ranking.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        let total: f32 = ranking.iter().map(|(_, score)| score).sum();
+        assert!((total - 1.0).abs() < 1e-4);
+        assert!((theme_margin(&ranking) - (ranking[0].1 - ranking[1].1)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn can_parse_slot_status() {
+        assert_eq!(SlotStatus::parse(""), SlotStatus::default());
+        assert_eq!(
+            SlotStatus::parse("2 OWNED\n"),
+            SlotStatus {
+                owned: Some(2),
+                crafted: false
+            }
+        );
+        assert_eq!(
+            SlotStatus::parse("✓ 1 0wned"),
+            SlotStatus {
+                owned: Some(1),
+                crafted: true
+            }
+        );
+        assert_eq!(
+            SlotStatus::parse("v 12 Ovned"),
+            SlotStatus {
+                owned: Some(12),
+                crafted: true
+            }
+        );
+    }
+
+    #[test]
+    fn can_split_lines() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(200, 80, |x, y| {
+            let first_line = (10..30).contains(&y) && (20..180).contains(&x);
+            let second_line = (40..60).contains(&y) && (60..140).contains(&x);
+            if first_line || second_line || (x, y) == (5, 75) {
+                Rgb([0; 3])
+            } else {
+                Rgb([255; 3])
+            }
+        }));
+
+        let lines = split_lines(&image);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].dimensions(), (200, 30));
+        assert_eq!(lines[1].dimensions(), (200, 30));
+
+        let single_line = image.crop_imm(0, 0, 200, 35);
+        assert_eq!(split_lines(&single_line).len(), 1);
+    }
+
+    #[test]
+    fn can_locate_reward_boxes() {
+        let boxes = locate_reward_boxes(
+            &reward_screen(&[-1.5, -0.5, 0.5, 1.5], true),
+            &Theme::Vitruvian,
+        )
+        .unwrap();
+        assert_eq!(boxes.len(), 4);
+        for (rect, left) in boxes.iter().zip([476, 718, 960, 1202]) {
+            assert!(rect.x.abs_diff(left) <= 2, "{rect:?}");
+            assert!(rect.width.abs_diff(242) <= 2, "{rect:?}");
+            assert!(rect.y <= 460 && rect.y + rect.height >= 480, "{rect:?}");
+        }
+
+        let boxes =
+            locate_reward_boxes(&reward_screen(&[-1.0, 0.0, 1.0], false), &Theme::Vitruvian)
+                .unwrap();
+        assert_eq!(boxes.len(), 3);
+        assert!(boxes[1].x.abs_diff(839) <= 2, "{:?}", boxes[1]);
+
+        let empty = Frame::new(DynamicImage::ImageRgb8(RgbImage::new(1920, 1080)));
+        assert_eq!(locate_reward_boxes(&empty, &Theme::Vitruvian), None);
+    }
+
+    #[test]
+    fn can_fit_part_box() {
+        let frame = reward_screen(&[-1.5, -0.5, 0.5, 1.5], true);
+        let fit = fit_part_box(&frame, &Theme::Vitruvian);
+        assert_eq!(fit.search_area, reward_search_area(&frame));
+        assert_eq!(
+            fit.search_area,
+            Rect {
+                x: 476,
+                y: 411,
+                width: 968,
+                height: 88
+            }
+        );
+        assert!((0.5..=1.0).contains(&fit.scale), "{fit:?}");
+        let (part, area) = (fit.part_box, fit.search_area);
+        assert!(part.x >= area.x && part.x + part.width <= area.x + area.width);
+        assert!(part.y >= area.y && part.y + part.height <= area.y + area.height);
+    }
+
+    #[test]
+    fn reads_nothing_without_a_theme() {
+        // A corner of the screen away from the columns sampled for the theme
+        let frame = Frame {
+            image: DynamicImage::new_rgb8(10, 10),
+            x: 1800,
+            y: 0,
+            screen_width: 1920,
+            screen_height: 1080,
+        };
+        assert!(rank_themes(&frame).is_empty());
+        assert_eq!(detect_theme(&frame), None);
+        assert!(detect_rewards(frame, None, &OcrOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn can_crop_to_the_reward_area() {
+        let frame = reward_screen(&[-1.5, -0.5, 0.5, 1.5], true);
+        let cropped = Frame::reward_area(&frame.image);
+        let area = reward_area(1920, 1080);
+        assert_eq!((cropped.x, cropped.y), (area.x, area.y));
+        assert_eq!(cropped.image.dimensions(), (area.width, area.height));
+        assert!(area.width * area.height * 10 < 1920 * 1080);
+
+        // Everything is found at the same place on the screen
+        let offset = |rect: Rect| Rect {
+            x: rect.x + area.x,
+            y: rect.y + area.y,
+            ..rect
+        };
+        let search_area = reward_search_area(&frame);
+        assert_eq!(offset(reward_search_area(&cropped)), search_area);
+        let fit = fit_part_box(&cropped, &Theme::Vitruvian);
+        assert_eq!(
+            offset(fit.part_box),
+            fit_part_box(&frame, &Theme::Vitruvian).part_box
+        );
+        let boxes = locate_reward_boxes(&cropped, &Theme::Vitruvian).unwrap();
+        assert_eq!(
+            boxes.into_iter().map(offset).collect::<Vec<_>>(),
+            locate_reward_boxes(&frame, &Theme::Vitruvian).unwrap()
+        );
+        assert!(status_region(&cropped, &reward_search_area(&cropped)).is_some());
+        assert_eq!(detect_theme(&cropped), Some(Theme::Vitruvian));
+    }
+
+    #[test]
+    fn keeps_the_ocr_language_once_selected() {
+        set_ocr_language("eng").unwrap();
+        set_ocr_language("eng").unwrap();
+        assert!(set_ocr_language("deu").is_err());
+    }
+}
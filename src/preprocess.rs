@@ -0,0 +1,315 @@
+use image::{DynamicImage, GenericImageView, Pixel, Rgb, RgbImage};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::theme::Theme;
+
+/// Steps turning a reward name image into black text on white for OCR, in the order they are
+/// applied. Every stage can be toggled, by default only the theme colour threshold is applied.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preprocessing {
+    /// Thresholds the brightness using Otsu's method if the theme colours match no text
+    pub otsu_fallback: bool,
+    /// Integer factor to enlarge the text by, tesseract struggles with the small text below 1440p
+    pub upscale: u32,
+    /// Removes specks smaller than a stroke and closes small gaps within letters
+    pub morphology: bool,
+    /// Straightens text that is slightly slanted by up to 5 degrees
+    pub deskew: bool,
+    /// Width of the white border added around the text in pixels
+    pub padding: u32,
+}
+
+impl Default for Preprocessing {
+    fn default() -> Self {
+        Preprocessing {
+            otsu_fallback: false,
+            upscale: 1,
+            morphology: false,
+            deskew: false,
+            padding: 0,
+        }
+    }
+}
+
+/// Text pixels of a binarised image
+#[derive(Clone, Debug, PartialEq)]
+struct Mask {
+    width: u32,
+    height: u32,
+    pixels: Vec<bool>,
+}
+
+impl Mask {
+    fn from_fn(width: u32, height: u32, text: impl Fn(u32, u32) -> bool) -> Mask {
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| text(x, y))
+            .collect();
+        Mask {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Whether (x, y) is text, everything outside the image is background
+    fn get(&self, x: i64, y: i64) -> bool {
+        (0..self.width as i64).contains(&x)
+            && (0..self.height as i64).contains(&y)
+            && self.pixels[(y * self.width as i64 + x) as usize]
+    }
+
+    fn count(&self) -> usize {
+        self.pixels.iter().filter(|&&text| text).count()
+    }
+
+    fn neighbourhood(&self, x: u32, y: u32) -> impl Iterator<Item = bool> + '_ {
+        let (x, y) = (x as i64, y as i64);
+        (-1..=1).flat_map(move |dy| (-1..=1).map(move |dx| self.get(x + dx, y + dy)))
+    }
+
+    fn erode(&self) -> Mask {
+        Mask::from_fn(self.width, self.height, |x, y| {
+            self.neighbourhood(x, y).all(|text| text)
+        })
+    }
+
+    fn dilate(&self) -> Mask {
+        Mask::from_fn(self.width, self.height, |x, y| {
+            self.neighbourhood(x, y).any(|text| text)
+        })
+    }
+
+    fn upscale(&self, factor: u32) -> Mask {
+        Mask::from_fn(self.width * factor, self.height * factor, |x, y| {
+            self.get((x / factor) as i64, (y / factor) as i64)
+        })
+    }
+
+    fn pad(&self, padding: u32) -> Mask {
+        let padding_offset = padding as i64;
+        Mask::from_fn(
+            self.width + 2 * padding,
+            self.height + 2 * padding,
+            |x, y| self.get(x as i64 - padding_offset, y as i64 - padding_offset),
+        )
+    }
+
+    /// Shifts every column vertically by `slope` pixels per column
+    fn shear(&self, slope: f32) -> Mask {
+        Mask::from_fn(self.width, self.height, |x, y| {
+            self.get(x as i64, y as i64 + (x as f32 * slope).round() as i64)
+        })
+    }
+
+    /// How sharply the text is concentrated in rows, straight text has the highest score
+    fn row_sharpness(&self, slope: f32) -> f32 {
+        let mut rows = vec![0.0_f32; self.height as usize * 2];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.get(x as i64, y as i64) {
+                    let row = y as f32 - x as f32 * slope + self.height as f32 / 2.0;
+                    if let Some(count) = rows.get_mut(row.round().max(0.0) as usize) {
+                        *count += 1.0;
+                    }
+                }
+            }
+        }
+        rows.iter().map(|count| count * count).sum()
+    }
+
+    fn deskew(&self) -> Mask {
+        let slope = (-10..=10)
+            .map(|step| (step as f32 * 0.5).to_radians().tan())
+            .max_by(|a, b| {
+                self.row_sharpness(*a)
+                    .total_cmp(&self.row_sharpness(*b))
+                    // Prefer leaving the image untouched on ties
+                    .then_with(|| b.abs().total_cmp(&a.abs()))
+            })
+            .unwrap_or(0.0);
+        debug!("Deskew slope: {slope}");
+        if slope == 0.0 {
+            self.clone()
+        } else {
+            self.shear(slope)
+        }
+    }
+
+    fn into_image(self) -> DynamicImage {
+        let image = RgbImage::from_fn(self.width, self.height, |x, y| {
+            if self.get(x as i64, y as i64) {
+                Rgb([0; 3])
+            } else {
+                Rgb([255; 3])
+            }
+        });
+        DynamicImage::ImageRgb8(image)
+    }
+}
+
+/// Brightness threshold separating the image into two classes with the least variance within each
+fn otsu_threshold(histogram: &[usize; 256]) -> u8 {
+    let total: usize = histogram.iter().sum();
+    let weighted_total: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, &count)| value as f64 * count as f64)
+        .sum();
+
+    let mut best = (0, 0.0);
+    let mut background = 0;
+    let mut weighted_background = 0.0;
+    for (value, &count) in histogram.iter().enumerate() {
+        background += count;
+        weighted_background += value as f64 * count as f64;
+        let foreground = total - background;
+        if background == 0 || foreground == 0 {
+            continue;
+        }
+        let background_mean = weighted_background / background as f64;
+        let foreground_mean = (weighted_total - weighted_background) / foreground as f64;
+        let variance =
+            background as f64 * foreground as f64 * (background_mean - foreground_mean).powi(2);
+        if variance > best.1 {
+            best = (value, variance);
+        }
+    }
+    best.0 as u8
+}
+
+impl Preprocessing {
+    /// Turns an unfiltered reward name image into black text on white
+    pub fn apply(&self, image: &DynamicImage, theme: &Theme) -> DynamicImage {
+        let filter = theme.resolved();
+        let mut mask = Mask::from_fn(image.width(), image.height(), |x, y| {
+            filter.matches(image.get_pixel(x, y).to_rgb())
+        });
+
+        // Less than a single line through the image can't be any text
+        if self.otsu_fallback && mask.count() < image.width() as usize {
+            let luma = image.to_luma8();
+            let mut histogram = [0; 256];
+            luma.pixels()
+                .for_each(|pixel| histogram[pixel.0[0] as usize] += 1);
+            let threshold = otsu_threshold(&histogram);
+            debug!("Theme matched no text, using brightness threshold {threshold}");
+            // The reward text is brighter than the background
+            mask = Mask::from_fn(image.width(), image.height(), |x, y| {
+                luma.get_pixel(x, y).0[0] > threshold
+            });
+        }
+        if self.upscale > 1 {
+            mask = mask.upscale(self.upscale);
+        }
+        if self.morphology {
+            // Opening removes specks, closing fills gaps
+            mask = mask.erode().dilate().dilate().erode();
+        }
+        if self.deskew {
+            mask = mask.deskew();
+        }
+        if self.padding > 0 {
+            mask = mask.pad(self.padding);
+        }
+
+        mask.into_image()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::Rgb;
+
+    use super::*;
+
+    const TEXT: Rgb<u8> = Rgb([190, 169, 102]);
+
+    /// A dark image with a horizontal bar of text coloured pixels
+    fn text_image(color: Rgb<u8>, slope: f32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(100, 40, |x, y| {
+            let bar_top = 15.0 + x as f32 * slope;
+            if (10..90).contains(&x) && (bar_top..bar_top + 6.0).contains(&(y as f32)) {
+                color
+            } else {
+                Rgb([20, 20, 30])
+            }
+        }))
+    }
+
+    fn text_pixels(image: &DynamicImage) -> usize {
+        image
+            .to_rgb8()
+            .pixels()
+            .filter(|pixel| pixel.0 == [0; 3])
+            .count()
+    }
+
+    #[test]
+    fn default_only_thresholds() {
+        let image = text_image(TEXT, 0.0);
+        let processed = Preprocessing::default().apply(&image, &Theme::Vitruvian);
+        assert_eq!(processed.dimensions(), (100, 40));
+        assert_eq!(text_pixels(&processed), 80 * 6);
+    }
+
+    #[test]
+    fn can_upscale_and_pad() {
+        let preprocessing = Preprocessing {
+            upscale: 3,
+            padding: 10,
+            ..Preprocessing::default()
+        };
+        let processed = preprocessing.apply(&text_image(TEXT, 0.0), &Theme::Vitruvian);
+        assert_eq!(processed.dimensions(), (320, 140));
+        assert_eq!(text_pixels(&processed), 80 * 6 * 9);
+    }
+
+    #[test]
+    fn morphology_removes_specks() {
+        let mut image = text_image(TEXT, 0.0).to_rgb8();
+        image.put_pixel(5, 5, TEXT);
+        let preprocessing = Preprocessing {
+            morphology: true,
+            ..Preprocessing::default()
+        };
+        let processed = preprocessing.apply(&DynamicImage::ImageRgb8(image), &Theme::Vitruvian);
+        assert_eq!(processed.to_rgb8().get_pixel(5, 5).0, [255; 3]);
+        assert_eq!(text_pixels(&processed), 80 * 6);
+    }
+
+    #[test]
+    fn falls_back_to_brightness_threshold() {
+        let image = text_image(Rgb([230, 230, 230]), 0.0);
+        let preprocessing = Preprocessing {
+            otsu_fallback: true,
+            ..Preprocessing::default()
+        };
+        assert_eq!(
+            text_pixels(&Preprocessing::default().apply(&image, &Theme::Vitruvian)),
+            0
+        );
+        assert_eq!(
+            text_pixels(&preprocessing.apply(&image, &Theme::Vitruvian)),
+            80 * 6
+        );
+    }
+
+    #[test]
+    fn can_deskew() {
+        let preprocessing = Preprocessing {
+            deskew: true,
+            ..Preprocessing::default()
+        };
+        let processed = preprocessing.apply(&text_image(TEXT, 0.05), &Theme::Vitruvian);
+        let rows: Vec<_> = processed
+            .to_rgb8()
+            .rows()
+            .map(|row| row.filter(|pixel| pixel.0 == [0; 3]).count())
+            .filter(|&count| count > 0)
+            .collect();
+        assert!(rows.len() <= 8, "{rows:?}");
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{Database, Item},
+    versioned::{self, Versioned},
+};
+
+lazy_static! {
+    static ref BUNDLED: RewardCatalogue =
+        RewardCatalogue::from_json(include_str!("../data/rewards.json"))
+            .expect("Bundled reward catalogue is invalid");
+}
+
+/// Rewards that aren't prime parts, such as Forma or Kuva, which the price data doesn't cover
+#[derive(Clone, Debug, Deserialize)]
+pub struct RewardCatalogue {
+    pub version: u32,
+    pub rewards: Vec<Reward>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Reward {
+    pub name: String,
+    /// Reference price, e.g. what the crafted item costs
+    pub platinum: f32,
+    /// Share of the reference price the reward is worth
+    #[serde(default = "default_fraction")]
+    pub fraction: f32,
+    #[serde(default)]
+    pub ducats: usize,
+}
+
+/// Changes to a catalogue entry from the config file, unknown names add a new reward
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RewardOverride {
+    pub platinum: Option<f32>,
+    pub fraction: Option<f32>,
+}
+
+fn default_fraction() -> f32 {
+    1.0
+}
+
+impl Versioned for RewardCatalogue {
+    const VERSION: u32 = 1;
+    const KIND: &'static str = "reward catalogue";
+}
+
+impl Reward {
+    pub fn value(&self) -> f32 {
+        self.platinum * self.fraction
+    }
+}
+
+impl RewardCatalogue {
+    /// The catalogue shipped in `data/rewards.json`
+    pub fn bundled() -> &'static RewardCatalogue {
+        &BUNDLED
+    }
+
+    pub fn from_json(text: &str) -> Result<RewardCatalogue, anyhow::Error> {
+        versioned::from_json(text)
+    }
+
+    pub fn with_overrides(&self, overrides: &HashMap<String, RewardOverride>) -> RewardCatalogue {
+        let mut catalogue = self.clone();
+        for (name, reward_override) in overrides.iter() {
+            let index = match catalogue
+                .rewards
+                .iter()
+                .position(|reward| &reward.name == name)
+            {
+                Some(index) => index,
+                None => {
+                    catalogue.rewards.push(Reward {
+                        name: name.to_owned(),
+                        platinum: 0.0,
+                        fraction: default_fraction(),
+                        ducats: 0,
+                    });
+                    catalogue.rewards.len() - 1
+                }
+            };
+            let reward = &mut catalogue.rewards[index];
+            if let Some(platinum) = reward_override.platinum {
+                reward.platinum = platinum;
+            }
+            if let Some(fraction) = reward_override.fraction {
+                reward.fraction = fraction;
+            }
+        }
+        catalogue
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Reward> {
+        self.rewards.iter().find(|reward| reward.name == name)
+    }
+}
+
+impl Database {
+    /// Adds every catalogue reward as an item, replacing the values of already known ones
+    pub fn with_rewards(mut self, catalogue: &RewardCatalogue) -> Database {
+        let items = self.items_mut();
+        for reward in catalogue.rewards.iter() {
+            match items.iter_mut().find(|item| item.name == reward.name) {
+                Some(item) => {
+                    item.platinum = reward.value();
+                    item.ducats = reward.ducats;
+                }
+                None => items.push(Item::new(&reward.name, reward.value(), reward.ducats)),
+            }
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_value_rewards() {
+        let db = Database::from_fixture();
+        // A third of the 35 platinum a bundle of three crafted Forma costs
+        let reward = RewardCatalogue::bundled().get("Forma Blueprint").unwrap();
+        assert_eq!(reward.platinum, 35.0);
+        assert!((reward.value() - 35.0 / 3.0).abs() < 0.001);
+        let forma = db.find_item_exact("Forma Blueprint").unwrap();
+        assert_eq!(forma.platinum, reward.value());
+        assert!(db.find_item_exact("Kuva").is_some());
+        assert!(db.find_item_exact("Orokin Reactor Blueprint").is_some());
+        assert_eq!(
+            db.find_item("Riven Sliver", None).unwrap().name,
+            "Riven Sliver"
+        );
+        for name in [
+            "Exilus Warframe Adapter Blueprint",
+            "Orokin Catalyst Blueprint",
+            "Ayatan Anasa Sculpture",
+        ] {
+            assert!(db.find_item_exact(name).unwrap().platinum > 0.0, "{name}");
+        }
+
+        // Short names only match exactly
+        assert_eq!(db.find_item("Kuva", None).unwrap().name, "Kuva");
+        assert!(db.find_item("Kuvo", None).is_none());
+        assert!(db.find_item("Ends", None).is_none());
+        assert!(db.find_item_by_name("Endu").is_none());
+        assert_eq!(db.find_item_by_name("endo").unwrap().name, "Endo");
+    }
+
+    #[test]
+    fn can_override_rewards() {
+        let overrides = HashMap::from([
+            (
+                "Forma Blueprint".to_string(),
+                RewardOverride {
+                    platinum: Some(12.0),
+                    fraction: Some(0.5),
+                },
+            ),
+            (
+                "Vitus Essence".to_string(),
+                RewardOverride {
+                    platinum: Some(4.0),
+                    fraction: None,
+                },
+            ),
+        ]);
+        let catalogue = RewardCatalogue::bundled().with_overrides(&overrides);
+        assert_eq!(catalogue.get("Forma Blueprint").unwrap().value(), 6.0);
+
+        let db = Database::builder()
+            .add_item(Item::new("Forma Blueprint", 0.0, 0))
+            .build()
+            .with_rewards(&catalogue);
+        assert_eq!(db.find_item_exact("Forma Blueprint").unwrap().platinum, 6.0);
+        assert_eq!(db.find_item_exact("Vitus Essence").unwrap().platinum, 4.0);
+        assert_eq!(
+            db.items()
+                .iter()
+                .filter(|item| item.name == "Forma Blueprint")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        assert!(RewardCatalogue::from_json(r#"{"version": 2, "rewards": []}"#).is_err());
+    }
+}
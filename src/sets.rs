@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{Database, Item},
+    ocr::SlotStatus,
+    wfinfo_data::{item_data::EquipmentItem, price_data::PriceItem},
+};
+
+/// Owned item counts, keyed by item name
+pub type Inventory = HashMap<String, usize>;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ItemSet {
+    /// Name of the set as traded, e.g. "Octavia Prime Set"
+    pub name: String,
+    pub platinum: Option<f32>,
+    pub parts: Vec<SetPart>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SetPart {
+    pub name: String,
+    pub quantity: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct SetProgress<'a> {
+    pub set: &'a ItemSet,
+    pub owned: usize,
+    pub required: usize,
+    /// Parts still needed along with how many of each are missing
+    pub missing: Vec<(&'a str, usize)>,
+}
+
+impl ItemSet {
+    pub(crate) fn from_equipment(
+        name: &str,
+        equipment_item: &EquipmentItem,
+        price_table: &HashMap<String, PriceItem>,
+    ) -> ItemSet {
+        let name = format!("{name} Set");
+        let platinum = price_table.get(&name).map(|price| price.custom_avg);
+        let mut parts: Vec<_> = equipment_item
+            .parts
+            .iter()
+            .map(|(name, ducat_item)| SetPart {
+                name: name.to_owned(),
+                quantity: ducat_item.count,
+            })
+            .collect();
+        parts.sort_by(|a, b| a.name.cmp(&b.name));
+
+        ItemSet {
+            name,
+            platinum,
+            parts,
+        }
+    }
+
+    pub fn contains(&self, item_name: &str) -> bool {
+        self.parts.iter().any(|part| part.name == item_name)
+    }
+
+    /// Combined price of all parts, or `None` if any part has no known price
+    pub fn parts_value(&self, database: &Database) -> Option<f32> {
+        self.parts
+            .iter()
+            .map(|part| {
+                database
+                    .find_item_exact(&part.name)
+                    .map(|item| item.platinum * part.quantity as f32)
+            })
+            .sum()
+    }
+
+    /// How much more the set sells for compared to selling its parts individually
+    pub fn set_premium(&self, database: &Database) -> Option<f32> {
+        Some(self.platinum? - self.parts_value(database)?)
+    }
+
+    pub fn progress<'a>(&'a self, inventory: &Inventory) -> SetProgress<'a> {
+        let mut owned = 0;
+        let mut required = 0;
+        let mut missing = Vec::new();
+        for part in self.parts.iter() {
+            let have = inventory.get(&part.name).copied().unwrap_or(0);
+            owned += have.min(part.quantity);
+            required += part.quantity;
+            if have < part.quantity {
+                missing.push((part.name.as_str(), part.quantity - have));
+            }
+        }
+
+        SetProgress {
+            set: self,
+            owned,
+            required,
+            missing,
+        }
+    }
+}
+
+impl SetProgress<'_> {
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    pub fn completion(&self) -> f32 {
+        self.owned as f32 / self.required as f32
+    }
+
+    /// Whether receiving a single `item` would complete this set
+    pub fn completed_by(&self, item: &Item) -> bool {
+        self.missing == [(item.name.as_str(), 1)]
+    }
+}
+
+impl Database {
+    /// Incomplete sets with at least one owned part, closest to completion first
+    pub fn closest_sets(&self, inventory: &Inventory) -> Vec<SetProgress<'_>> {
+        let mut progress: Vec<_> = self
+            .sets()
+            .iter()
+            .map(|set| set.progress(inventory))
+            .filter(|progress| progress.owned > 0 && !progress.is_complete())
+            .collect();
+        progress.sort_by(|a, b| {
+            let missing = |progress: &SetProgress| progress.required - progress.owned;
+            missing(a)
+                .cmp(&missing(b))
+                .then_with(|| b.completion().total_cmp(&a.completion()))
+                .then_with(|| a.set.name.cmp(&b.set.name))
+        });
+        progress
+    }
+
+    /// Whether more of `item` are needed for its set according to the reward slot,
+    /// `None` if the slot showed no owned count
+    pub fn still_needed(&self, item: &Item, status: &SlotStatus) -> Option<bool> {
+        let owned = status.owned?;
+        let quantity = self
+            .set_of(&item.name)
+            .and_then(|set| set.parts.iter().find(|part| part.name == item.name))
+            .map_or(1, |part| part.quantity);
+        Some(!status.crafted && owned < quantity)
+    }
+
+    /// Returns the index of every reward that would complete a set along with that set
+    pub fn rewards_completing_sets(
+        &self,
+        inventory: &Inventory,
+        rewards: &[Option<&Item>],
+    ) -> Vec<(usize, &ItemSet)> {
+        rewards
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| {
+                let item = (*item)?;
+                let set = self.set_of(&item.name)?;
+                set.progress(inventory)
+                    .completed_by(item)
+                    .then_some((index, set))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_value_sets() {
+        let db = Database::from_fixture();
+
+        let set = db
+            .find_set("Dual Kamas Prime Set")
+            .expect("Failed to find Dual Kamas Prime Set in database");
+        let blade = set
+            .parts
+            .iter()
+            .find(|part| part.name == "Dual Kamas Prime Blade")
+            .expect("Set is missing its blade");
+        assert_eq!(blade.quantity, 2);
+
+        let expected: f32 = set
+            .parts
+            .iter()
+            .map(|part| db.find_item_exact(&part.name).unwrap().platinum * part.quantity as f32)
+            .sum();
+        assert_eq!(set.parts_value(&db), Some(expected));
+        assert_eq!(set.set_premium(&db), Some(set.platinum.unwrap() - expected));
+    }
+
+    #[test]
+    fn can_track_set_completion() {
+        let db = Database::from_fixture();
+
+        let set = db.find_set("Octavia Prime Set").unwrap();
+        let mut inventory: Inventory = set
+            .parts
+            .iter()
+            .map(|part| (part.name.clone(), part.quantity))
+            .collect();
+        inventory.insert("Octavia Prime Systems".to_string(), 0);
+        inventory.insert("Braton Prime Stock".to_string(), 1);
+
+        let closest = db.closest_sets(&inventory);
+        assert_eq!(closest[0].set.name, "Octavia Prime Set");
+        assert_eq!(closest[0].missing, [("Octavia Prime Systems", 1)]);
+        assert!(closest
+            .iter()
+            .any(|progress| progress.set.name == "Braton Prime Set"));
+
+        let rewards = [
+            db.find_item_exact("Braton Prime Barrel"),
+            None,
+            db.find_item_exact("Octavia Prime Systems"),
+        ];
+        let completing = db.rewards_completing_sets(&inventory, &rewards);
+        assert_eq!(completing.len(), 1);
+        assert_eq!(completing[0].0, 2);
+        assert_eq!(completing[0].1.name, "Octavia Prime Set");
+    }
+
+    #[test]
+    fn can_tell_needed_items() {
+        let db = Database::from_fixture();
+        let blade = db.find_item_exact("Dual Kamas Prime Blade").unwrap();
+        let status = |owned, crafted| SlotStatus { owned, crafted };
+
+        assert_eq!(db.still_needed(blade, &status(None, false)), None);
+        assert_eq!(db.still_needed(blade, &status(Some(1), false)), Some(true));
+        assert_eq!(db.still_needed(blade, &status(Some(2), false)), Some(false));
+        assert_eq!(db.still_needed(blade, &status(Some(0), true)), Some(false));
+
+        let forma = db.find_item_exact("Forma Blueprint").unwrap();
+        assert_eq!(db.still_needed(forma, &status(Some(0), false)), Some(true));
+    }
+}
@@ -1,10 +1,23 @@
-use std::ops::Range;
+use std::{
+    fs::read_to_string,
+    io::ErrorKind,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{OnceLock, RwLock},
+};
 
+use anyhow::{bail, Context};
 use image::Rgb;
+use lazy_static::lazy_static;
 use ordered_float::OrderedFloat;
 use palette::{FromColor, Hsl, RgbHue, Srgb};
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    config::Config,
+    versioned::{self, Versioned},
+};
+
 #[derive(Clone, Debug, PartialEq, Hash, Eq, Serialize, Deserialize)]
 pub struct HslRange<T> {
     pub hue: Range<T>,
@@ -50,6 +63,292 @@ pub enum Theme {
     DarkLotus,
     Zephyr,
     Custom(HslRange<OrderedFloat<f32>>),
+    /// A theme only known from the theme definitions, e.g. one added to the game recently
+    Named(String),
+}
+
+/// Themes built into the game
+pub const BUILT_IN: [Theme; 15] = [
+    Theme::Vitruvian,
+    Theme::Stalker,
+    Theme::Baruuk,
+    Theme::Corpus,
+    Theme::Fortuna,
+    Theme::Grineer,
+    Theme::Lotus,
+    Theme::Nidus,
+    Theme::Orokin,
+    Theme::Tenno,
+    Theme::HighContrast,
+    Theme::Legacy,
+    Theme::Equinox,
+    Theme::DarkLotus,
+    Theme::Zephyr,
+];
+
+lazy_static! {
+    static ref PRESETS: RwLock<Vec<Theme>> = RwLock::new(Vec::new());
+    static ref BUNDLED: ThemeDefinitions =
+        ThemeDefinitions::from_json(include_str!("../data/themes.json"))
+            .expect("Bundled theme definitions are invalid");
+}
+
+/// The definitions in use, fixed once the first theme colour is looked up
+static DEFINITIONS: OnceLock<ResolvedThemes> = OnceLock::new();
+
+/// Adds custom themes, e.g. saved presets, to the candidates of [`Theme::iter`]
+pub fn set_preset_themes(themes: Vec<Theme>) {
+    *PRESETS.write().unwrap() = themes;
+}
+
+/// Selects the theme definitions, has to be called before any theme colour is used
+pub fn set_theme_definitions(definitions: &ThemeDefinitions) -> Result<(), anyhow::Error> {
+    if DEFINITIONS.set(ResolvedThemes::new(definitions)).is_err() {
+        bail!("Theme definitions can't be changed once they are in use");
+    }
+    Ok(())
+}
+
+fn definitions() -> &'static ResolvedThemes {
+    DEFINITIONS.get_or_init(|| ResolvedThemes::new(ThemeDefinitions::bundled()))
+}
+
+/// Colours and threshold rules of all themes, from `data/themes.json` and the user's overrides
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ThemeDefinitions {
+    pub version: u32,
+    pub themes: Vec<ThemeDefinition>,
+}
+
+impl Versioned for ThemeDefinitions {
+    const VERSION: u32 = 1;
+    const KIND: &'static str = "theme definitions";
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ThemeDefinition {
+    /// Name of a built-in [`Theme`] variant, or of a new theme
+    pub name: String,
+    pub primary: [u8; 3],
+    pub secondary: [u8; 3],
+    #[serde(default)]
+    pub threshold: ThresholdRule,
+}
+
+/// Decides which pixels belong to the reward text of a theme
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "lowercase")]
+pub enum ThresholdRule {
+    /// Colours closer than `max` to the primary or secondary colour, see [`color_difference`]
+    Distance { max: f32 },
+    /// Colours within the given limits, hue in degrees from -180 to 180
+    Hsl {
+        #[serde(default)]
+        hue: Limit,
+        #[serde(default)]
+        saturation: Limit,
+        #[serde(default)]
+        lightness: Limit,
+    },
+}
+
+impl Default for ThresholdRule {
+    fn default() -> Self {
+        ThresholdRule::Distance { max: 0.2 }
+    }
+}
+
+/// Bounds of a colour component, missing bounds don't limit it
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limit {
+    /// Smallest allowed value
+    pub min: Option<f32>,
+    /// Largest allowed value
+    pub max: Option<f32>,
+    /// Values have to be smaller than this
+    pub below: Option<f32>,
+}
+
+impl Limit {
+    pub fn contains(&self, value: f32) -> bool {
+        !(self.min.is_some_and(|min| value < min)
+            || self.max.is_some_and(|max| value > max)
+            || self.below.is_some_and(|below| value >= below))
+    }
+}
+
+impl ThresholdRule {
+    pub fn matches(&self, test: Hsl, primary: Hsl, secondary: Hsl) -> bool {
+        match self {
+            ThresholdRule::Distance { max } => {
+                color_difference((primary, test)) < *max
+                    || color_difference((secondary, test)) < *max
+            }
+            ThresholdRule::Hsl {
+                hue,
+                saturation,
+                lightness,
+            } => {
+                hue.contains(test.hue.to_degrees())
+                    && saturation.contains(test.saturation)
+                    && lightness.contains(test.lightness)
+            }
+        }
+    }
+}
+
+impl ThemeDefinitions {
+    /// The definitions shipped in `data/themes.json`
+    pub fn bundled() -> &'static ThemeDefinitions {
+        &BUNDLED
+    }
+
+    /// `$XDG_CONFIG_HOME/wfinfo/theme_definitions.json`
+    pub fn path() -> PathBuf {
+        Config::directory().join("theme_definitions.json")
+    }
+
+    pub fn from_json(text: &str) -> Result<ThemeDefinitions, anyhow::Error> {
+        versioned::from_json(text)
+    }
+
+    /// The bundled definitions with those in `path` applied, a missing file changes nothing
+    pub fn load(path: &Path) -> Result<ThemeDefinitions, anyhow::Error> {
+        match read_to_string(path) {
+            Ok(text) => Ok(Self::bundled().with_overrides(
+                &Self::from_json(&text)
+                    .with_context(|| format!("Failed to parse {}", path.display()))?,
+            )),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::bundled().clone()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Replaces the definitions of the same name, definitions of unknown themes are added
+    pub fn with_overrides(&self, overrides: &ThemeDefinitions) -> ThemeDefinitions {
+        let mut definitions = self.clone();
+        for theme in overrides.themes.iter() {
+            match definitions
+                .themes
+                .iter_mut()
+                .find(|definition| definition.name == theme.name)
+            {
+                Some(definition) => *definition = theme.clone(),
+                None => definitions.themes.push(theme.clone()),
+            }
+        }
+        definitions
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ThemeDefinition> {
+        self.themes
+            .iter()
+            .find(|definition| definition.name == name)
+    }
+}
+
+/// A definition with its colours converted, looked up for every pixel
+#[derive(Clone, Debug)]
+struct ResolvedTheme {
+    primary: Hsl,
+    secondary: Hsl,
+    threshold: ThresholdRule,
+}
+
+impl ResolvedTheme {
+    fn new(definition: &ThemeDefinition) -> ResolvedTheme {
+        ResolvedTheme {
+            primary: rgb_to_hsl(definition.primary),
+            secondary: rgb_to_hsl(definition.secondary),
+            threshold: definition.threshold.clone(),
+        }
+    }
+}
+
+/// Decides which pixels belong to the reward text of a theme, see [`Theme::resolved`]
+#[derive(Copy, Clone, Debug)]
+pub struct ThresholdFilter<'a>(Filter<'a>);
+
+#[derive(Copy, Clone, Debug)]
+enum Filter<'a> {
+    Range(&'a HslRange<OrderedFloat<f32>>),
+    /// Themes missing from the definitions, e.g. from an old config file, match nothing
+    Defined(Option<&'static ResolvedTheme>),
+}
+
+impl ThresholdFilter<'_> {
+    pub fn matches(&self, color: Rgb<u8>) -> bool {
+        let test = rgb_to_hsl(color.0);
+        match self.0 {
+            Filter::Range(range) => {
+                range.hue.contains(&OrderedFloat(test.hue.to_degrees()))
+                    && range.saturation.contains(&OrderedFloat(test.saturation))
+                    && range.lightness.contains(&OrderedFloat(test.lightness))
+            }
+            Filter::Defined(resolved) => resolved.is_some_and(|resolved| {
+                resolved
+                    .threshold
+                    .matches(test, resolved.primary, resolved.secondary)
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ResolvedThemes {
+    /// In the order of [`BUILT_IN`]
+    built_in: Vec<ResolvedTheme>,
+    named: Vec<(String, ResolvedTheme)>,
+}
+
+impl ResolvedThemes {
+    fn new(definitions: &ThemeDefinitions) -> ResolvedThemes {
+        let built_in_names: Vec<_> = BUILT_IN.iter().map(|theme| format!("{theme:?}")).collect();
+        let built_in = built_in_names
+            .iter()
+            .map(|name| {
+                // Overrides are applied to the bundled definitions, so these can't be missing
+                ResolvedTheme::new(
+                    definitions
+                        .get(name)
+                        .or_else(|| ThemeDefinitions::bundled().get(name))
+                        .expect("Bundled theme definitions lack a built-in theme"),
+                )
+            })
+            .collect();
+        let named = definitions
+            .themes
+            .iter()
+            .filter(|definition| !built_in_names.contains(&definition.name))
+            .map(|definition| (definition.name.clone(), ResolvedTheme::new(definition)))
+            .collect();
+        ResolvedThemes { built_in, named }
+    }
+
+    fn get(&self, theme: &Theme) -> Option<&ResolvedTheme> {
+        match theme {
+            Theme::Custom(_) => None,
+            Theme::Named(name) => self
+                .named
+                .iter()
+                .find(|(named, _)| named == name)
+                .map(|(_, resolved)| resolved),
+            built_in => BUILT_IN
+                .iter()
+                .position(|theme| theme == built_in)
+                .map(|index| &self.built_in[index]),
+        }
+    }
+}
+
+fn rgb_to_hsl(color: [u8; 3]) -> Hsl {
+    let components = (
+        color[0] as f32 / 255.0,
+        color[1] as f32 / 255.0,
+        color[2] as f32 / 255.0,
+    );
+    Hsl::from_color(Srgb::from_components(components))
 }
 
 pub fn color_difference(colors: (Hsl, Hsl)) -> f32 {
@@ -61,172 +360,142 @@ pub fn color_difference(colors: (Hsl, Hsl)) -> f32 {
 
 impl Theme {
     pub fn closest_from_color(color: Rgb<u8>) -> (Theme, f32) {
-        let rgb = Srgb::from_components((
-            color.0[0] as f32 / 255.0,
-            color.0[1] as f32 / 255.0,
-            color.0[2] as f32 / 255.0,
-        ));
-        let hsl = Hsl::from_color(rgb);
-        Self::iter()
+        Self::closest_from_color_among(color, &Self::iter().collect::<Vec<_>>())
+    }
+
+    /// Like [`Theme::closest_from_color`], but only considering `candidates`
+    pub fn closest_from_color_among(color: Rgb<u8>, candidates: &[Theme]) -> (Theme, f32) {
+        let hsl = rgb_to_hsl(color.0);
+        candidates
+            .iter()
             .map(|theme| (theme.clone(), color_difference((theme.primary(), hsl))))
             .min_by(|a, b| a.1.total_cmp(&b.1))
             .unwrap()
     }
 
-    pub fn iter() -> std::slice::Iter<'static, Theme> {
-        [
-            Self::Vitruvian,
-            Self::Stalker,
-            Self::Baruuk,
-            Self::Corpus,
-            Self::Fortuna,
-            Self::Grineer,
-            Self::Lotus,
-            Self::Nidus,
-            Self::Orokin,
-            Self::Tenno,
-            Self::HighContrast,
-            Self::Legacy,
-            Self::Equinox,
-            Self::DarkLotus,
-            Self::Zephyr,
-        ]
-        .iter()
+    /// The built-in themes followed by the themes only known from the theme definitions
+    pub fn defined() -> Vec<Theme> {
+        let mut themes = BUILT_IN.to_vec();
+        themes.extend(
+            definitions()
+                .named
+                .iter()
+                .map(|(name, _)| Theme::Named(name.clone())),
+        );
+        themes
+    }
+
+    /// Every theme considered when detecting the theme: the defined ones followed by the presets
+    pub fn iter() -> std::vec::IntoIter<Theme> {
+        let mut themes = Self::defined();
+        themes.extend(PRESETS.read().unwrap().iter().cloned());
+        themes.into_iter()
+    }
+
+    /// Name used in the theme definitions and to select the theme, `None` for custom themes
+    pub fn name(&self) -> Option<String> {
+        match self {
+            Theme::Custom(_) => None,
+            Theme::Named(name) => Some(name.clone()),
+            built_in => Some(format!("{built_in:?}")),
+        }
     }
 
+    /// Whether `color` belongs to the reward text, use [`Theme::resolved`] for many pixels
     pub fn threshold_filter(&self, color: Rgb<u8>) -> bool {
-        let rgb = Srgb::from_components((
-            color.0[0] as f32 / 255.0,
-            color.0[1] as f32 / 255.0,
-            color.0[2] as f32 / 255.0,
-        ));
-        let test = Hsl::from_color(rgb);
+        self.resolved().matches(color)
+    }
 
-        let primary = self.primary();
-        let secondary = self.secondary();
+    /// Looks the theme's definition up once, for testing many pixels
+    pub fn resolved(&self) -> ThresholdFilter<'_> {
+        ThresholdFilter(match self {
+            Theme::Custom(range) => Filter::Range(range),
+            _ => Filter::Defined(definitions().get(self)),
+        })
+    }
 
+    pub fn primary(&self) -> Hsl {
         match self {
-            Theme::Equinox => test.saturation <= 0.2 && test.lightness >= 0.55,
-            Theme::Stalker => {
-                (0.61..1.00).contains(&test.saturation)
-                    && (0.25..0.65).contains(&test.lightness)
-                    && (-10.0..5.0).contains(&test.hue.to_degrees())
-            }
-            Theme::HighContrast => {
-                test.saturation >= 0.60
-                    && (0.23..0.45).contains(&test.lightness)
-                    && (-160.0..-145.0).contains(&test.hue.to_degrees())
-            }
-            Theme::Custom(range) => {
-                range.hue.contains(&OrderedFloat(test.hue.to_degrees()))
-                    && range.saturation.contains(&OrderedFloat(test.saturation))
-                    && range.lightness.contains(&OrderedFloat(test.lightness))
-            }
-            _ => {
-                color_difference((primary, test)) < 0.2 || color_difference((secondary, test)) < 0.2
-            }
+            Theme::Custom(range) => range.get_average(),
+            _ => definitions()
+                .get(self)
+                .map_or_else(Hsl::default, |resolved| resolved.primary),
         }
+    }
 
-        // hsv.hue.abs_diff_eq(&primary.hue, 4.0) && hsv.saturation >= 0.25 && hsv.value >= 0.42
-
-        // match self {
-        //     Theme::Vitruvian => {
-        //         test.hue.abs_diff_eq(&primary.hue, 4.0)
-        //             && test.saturation >= 0.25
-        //             && test.lightness >= 0.42
-        //     }
-        //     Theme::Stalker => {
-        //         test.hue.abs_diff_eq(&primary.hue, 4.0) && test.saturation >= 0.55
-        //             || test.hue.abs_diff_eq(&secondary.hue, 4.0)
-        //                 && test.saturation >= 0.66
-        //                 && test.lightness >= 0.25
-        //     }
-        //     Theme::Baruuk => {
-        //         test.hue.abs_diff_eq(&primary.hue, 2.0)
-        //             && test.saturation > 0.25
-        //             && test.lightness > 0.5
-        //     }
-        //     Theme::Corpus => {
-        //         test.hue.abs_diff_eq(&primary.hue, 3.0)
-        //             && test.saturation >= 0.35
-        //             && test.lightness >= 0.42
-        //     }
-        //     Theme::Fortuna => {
-        //         test.hue.abs_diff_eq(&primary.hue, 3.0) && test.lightness >= 0.35
-        //             || test.hue.abs_diff_eq(&secondary.hue, 4.0)
-        //                 && test.saturation >= 0.2
-        //                 && test.lightness >= 0.15
-        //     }
-        //     Theme::Grineer => todo!(),
-        //     Theme::Lotus => {
-        //         test.hue.abs_diff_eq(&primary.hue, 5.0)
-        //             && test.saturation >= 0.65
-        //             && primary.lightness.abs_diff_eq(&test.lightness, 0.1)
-        //     }
-        //     Theme::Nidus => todo!(),
-        //     Theme::Orokin => todo!(),
-        //     Theme::Tenno => todo!(),
-        //     Theme::HighContrast => todo!(),
-        //     Theme::Legacy => todo!(),
-        //     Theme::Equinox => todo!(),
-        //     Theme::DarkLotus => todo!(),
-        //     Theme::Zephyr => todo!(),
-        // }
+    pub fn secondary(&self) -> Hsl {
+        match self {
+            Theme::Custom(range) => range.get_average(),
+            _ => definitions()
+                .get(self)
+                .map_or_else(Hsl::default, |resolved| resolved.secondary),
+        }
     }
+}
 
-    pub fn primary(&self) -> Hsl {
-        let components = match self {
-            Theme::Vitruvian => (190, 169, 102),
-            Theme::Stalker => (153, 31, 35),
-            Theme::Baruuk => (238, 193, 105),
-            Theme::Corpus => (35, 201, 245),
-            Theme::Fortuna => (57, 105, 192),
-            Theme::Grineer => (255, 189, 102),
-            Theme::Lotus => (36, 184, 242),
-            Theme::Nidus => (140, 38, 92),
-            Theme::Orokin => (20, 41, 29),
-            Theme::Tenno => (9, 78, 106),
-            Theme::HighContrast => (2, 127, 217),
-            Theme::Legacy => (255, 255, 255),
-            Theme::Equinox => (158, 159, 167),
-            Theme::DarkLotus => (140, 119, 147),
-            Theme::Zephyr => (253, 132, 2),
-            Theme::Custom(range) => return range.get_average(),
-        };
+#[cfg(test)]
+mod test {
+    use super::*;
 
-        let components = (
-            components.0 as f32 / 255.0,
-            components.1 as f32 / 255.0,
-            components.2 as f32 / 255.0,
+    #[test]
+    fn bundled_definitions_cover_built_in_themes() {
+        for theme in BUILT_IN.iter() {
+            let name = theme.name().unwrap();
+            assert!(ThemeDefinitions::bundled().get(&name).is_some(), "{name}");
+        }
+        assert_eq!(
+            Theme::Vitruvian.primary(),
+            rgb_to_hsl([190, 169, 102]),
+            "Primary colours are converted from the definitions"
         );
-        Hsl::from_color(Srgb::from_components(components))
+        assert!(Theme::Vitruvian.threshold_filter(Rgb([190, 169, 102])));
+        assert!(!Theme::Vitruvian.threshold_filter(Rgb([20, 20, 30])));
     }
 
-    pub fn secondary(&self) -> Hsl {
-        let components = match self {
-            Theme::Vitruvian => (245, 227, 173),
-            Theme::Stalker => (255, 61, 51),
-            Theme::Baruuk => (236, 211, 162),
-            Theme::Corpus => (111, 229, 253),
-            Theme::Fortuna => (255, 115, 230),
-            Theme::Grineer => (255, 224, 153),
-            Theme::Lotus => (255, 241, 191),
-            Theme::Nidus => (245, 73, 93),
-            Theme::Orokin => (178, 125, 5),
-            Theme::Tenno => (6, 106, 74),
-            Theme::HighContrast => (255, 255, 0),
-            Theme::Legacy => (232, 213, 93),
-            Theme::Equinox => (232, 227, 227),
-            Theme::DarkLotus => (189, 169, 237),
-            Theme::Zephyr => (255, 53, 0),
-            Theme::Custom(range) => return range.get_average(),
+    #[test]
+    fn can_apply_threshold_rules() {
+        assert!(Theme::Equinox.threshold_filter(Rgb([200, 200, 205])));
+        assert!(!Theme::Equinox.threshold_filter(Rgb([90, 90, 95])));
+        // Stalker excludes fully saturated colours
+        assert!(Theme::Stalker.threshold_filter(Rgb([200, 30, 35])));
+        assert!(!Theme::Stalker.threshold_filter(Rgb([200, 0, 0])));
+
+        let limit = Limit {
+            min: Some(0.2),
+            max: None,
+            below: Some(0.5),
         };
+        assert!(limit.contains(0.2));
+        assert!(!limit.contains(0.5));
+    }
 
-        let components = (
-            components.0 as f32 / 255.0,
-            components.1 as f32 / 255.0,
-            components.2 as f32 / 255.0,
+    #[test]
+    fn can_override_definitions() {
+        let overrides = ThemeDefinitions::from_json(
+            r#"{"version": 1, "themes": [
+                {"name": "Vitruvian", "primary": [0, 0, 255], "secondary": [0, 0, 200]},
+                {"name": "Koumei", "primary": [200, 40, 40], "secondary": [240, 200, 90],
+                 "threshold": {"rule": "hsl", "hue": {"min": -20, "below": 20}}}
+            ]}"#,
+        )
+        .unwrap();
+        let definitions = ThemeDefinitions::bundled().with_overrides(&overrides);
+        assert_eq!(definitions.themes.len(), BUILT_IN.len() + 1);
+        assert_eq!(definitions.get("Vitruvian").unwrap().primary, [0, 0, 255]);
+
+        let resolved = ResolvedThemes::new(&definitions);
+        assert_eq!(
+            resolved.get(&Theme::Vitruvian).unwrap().primary,
+            rgb_to_hsl([0, 0, 255])
         );
-        Hsl::from_color(Srgb::from_components(components))
+        let koumei = resolved.get(&Theme::Named("Koumei".to_string())).unwrap();
+        assert!(koumei.threshold.matches(
+            rgb_to_hsl([200, 40, 40]),
+            koumei.primary,
+            koumei.secondary
+        ));
+        assert!(resolved.get(&Theme::Named("Unknown".to_string())).is_none());
+
+        assert!(ThemeDefinitions::from_json(r#"{"version": 2, "themes": []}"#).is_err());
     }
 }
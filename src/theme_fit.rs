@@ -0,0 +1,271 @@
+use std::{collections::BTreeMap, collections::HashMap, fs::read_to_string, path::Path};
+
+use anyhow::Context;
+use image::{io::Reader, DynamicImage};
+use log::{debug, info};
+
+use crate::{
+    database::Database,
+    ocr::{reward_image_to_reward_names_with, OcrOptions},
+    testing::Label,
+    theme::{HslRange, Theme},
+};
+
+/// Hue ranges tried before refining, as (centre, half width) in degrees
+const HUE_GRID_STEP: f32 = 20.0;
+const HUE_GRID_HALF_WIDTH: f32 = 10.0;
+/// Factors by which the refinement steps shrink, from coarse to fine
+const REFINEMENT_SCALES: [f32; 4] = [1.0, 0.5, 0.25, 0.125];
+const HUE_STEP: f32 = 8.0;
+const SATURATION_LIGHTNESS_STEP: f32 = 0.1;
+/// Passes over all bounds per step size, keeps the number of OCR runs bounded
+const MAX_PASSES: usize = 20;
+
+/// A screenshot of the reward screen along with the drop names of its rewards
+pub struct Sample {
+    /// File name relative to the `labels.json` listing it
+    pub name: String,
+    pub image: DynamicImage,
+    pub items: Vec<String>,
+}
+
+/// Loads the screenshots listed in a `labels.json` file, relative to the directory containing it
+pub fn load_samples(labels_path: &Path) -> Result<Vec<Sample>, anyhow::Error> {
+    let labels: BTreeMap<String, Label> = serde_json::from_str(
+        &read_to_string(labels_path)
+            .with_context(|| format!("Failed to read {}", labels_path.display()))?,
+    )
+    .with_context(|| format!("Failed to parse {}", labels_path.display()))?;
+    let directory = labels_path.parent().unwrap_or(Path::new(""));
+
+    labels
+        .into_iter()
+        .map(|(filename, label)| {
+            let path = directory.join(&filename);
+            let image = Reader::open(&path)
+                .and_then(|reader| reader.with_guessed_format())
+                .with_context(|| format!("Failed to open {}", path.display()))?
+                .decode()
+                .with_context(|| format!("Failed to decode {}", path.display()))?;
+            Ok(Sample {
+                name: filename,
+                image,
+                items: label.items,
+            })
+        })
+        .collect()
+}
+
+/// Number of labelled rewards matched by the names read from a screenshot. Empty labels mark
+/// rewards that aren't known items, they count as matched if no item is found for them.
+fn matched_labels(database: &Database, names: &[String], items: &[String]) -> usize {
+    items
+        .iter()
+        .enumerate()
+        .filter(|(index, expected)| {
+            let item = names
+                .get(*index)
+                .and_then(|name| database.find_item_multiline(name, None));
+            item.map_or("", |item| item.drop_name.as_str()) == expected.as_str()
+        })
+        .count()
+}
+
+/// Share of all labelled rewards that are read and matched correctly using the given colour range
+pub fn match_rate(
+    samples: &[Sample],
+    database: &Database,
+    options: &OcrOptions,
+    range: &HslRange<f32>,
+) -> f32 {
+    let total: usize = samples.iter().map(|sample| sample.items.len()).sum();
+    if total == 0 {
+        return 0.0;
+    }
+    let theme = Theme::Custom(range.to_ordered());
+    let matched: usize = samples
+        .iter()
+        .map(|sample| {
+            let names = reward_image_to_reward_names_with(
+                sample.image.clone(),
+                Some(theme.clone()),
+                options,
+            );
+            matched_labels(database, &names, &sample.items)
+        })
+        .sum();
+    matched as f32 / total as f32
+}
+
+/// Searches the colour range of a custom theme maximising `score`.
+///
+/// A grid of hue ranges is tried first, after which every bound is moved by shrinking steps
+/// for as long as that improves the score. Returns the best range along with its score.
+pub fn fit_hsl_range(mut score: impl FnMut(&HslRange<f32>) -> f32) -> (HslRange<f32>, f32) {
+    let mut scores = HashMap::new();
+    let mut evaluate = |range: &HslRange<f32>| -> f32 {
+        *scores
+            .entry(range.to_ordered())
+            .or_insert_with(|| score(range))
+    };
+
+    let mut best = (hue_range(-180.0 + HUE_GRID_STEP / 2.0), f32::NEG_INFINITY);
+    let mut centre = -180.0 + HUE_GRID_STEP / 2.0;
+    while centre < 180.0 {
+        let range = hue_range(centre);
+        let score = evaluate(&range);
+        debug!("Hue {:?}: {score}", range.hue);
+        if score > best.1 {
+            best = (range, score);
+        }
+        centre += HUE_GRID_STEP;
+    }
+    info!("Best hue range {:?} with a score of {}", best.0.hue, best.1);
+
+    for scale in REFINEMENT_SCALES {
+        for _ in 0..MAX_PASSES {
+            if best.1 >= 1.0 {
+                return best;
+            }
+            let mut improved = false;
+            for (dimension, end) in
+                (0..3).flat_map(|dimension| [(dimension, false), (dimension, true)])
+            {
+                let step = if dimension == 0 {
+                    HUE_STEP
+                } else {
+                    SATURATION_LIGHTNESS_STEP
+                } * scale;
+                for direction in [-1.0, 1.0] {
+                    let Some(range) = move_bound(&best.0, dimension, end, step * direction) else {
+                        continue;
+                    };
+                    let score = evaluate(&range);
+                    if score > best.1 {
+                        debug!("{range:?}: {score}");
+                        best = (range, score);
+                        improved = true;
+                    }
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+    }
+    best
+}
+
+/// Searches the colour range of a custom theme reading the labelled rewards of `samples`
+/// as well as possible, returns it along with the share of rewards it reads correctly
+pub fn fit_custom_theme(
+    samples: &[Sample],
+    database: &Database,
+    options: &OcrOptions,
+) -> (HslRange<f32>, f32) {
+    fit_hsl_range(|range| match_rate(samples, database, options, range))
+}
+
+/// The starting point for a hue, accepting any reasonably saturated and bright colour
+fn hue_range(centre: f32) -> HslRange<f32> {
+    HslRange {
+        hue: centre - HUE_GRID_HALF_WIDTH..centre + HUE_GRID_HALF_WIDTH,
+        saturation: 0.5..1.0,
+        lightness: 0.15..1.0,
+    }
+}
+
+/// Moves the start or end of the hue (0), saturation (1) or lightness (2) range.
+/// Returns `None` if the range would become empty or leave the valid values.
+fn move_bound(
+    range: &HslRange<f32>,
+    dimension: usize,
+    end: bool,
+    delta: f32,
+) -> Option<HslRange<f32>> {
+    let mut moved = range.clone();
+    // Ranges exclude their end, so it may lie past the largest value to include it
+    let (values, lower, upper) = match dimension {
+        0 => (&mut moved.hue, -180.0, 180.0 + HUE_STEP),
+        1 => (&mut moved.saturation, 0.0, 1.0 + SATURATION_LIGHTNESS_STEP),
+        _ => (&mut moved.lightness, 0.0, 1.0 + SATURATION_LIGHTNESS_STEP),
+    };
+    if end {
+        values.end += delta;
+    } else {
+        values.start += delta;
+    }
+    (values.start < values.end && values.start >= lower && values.end <= upper).then_some(moved)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, ops::Range};
+
+    use image::{Rgb, RgbImage};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Share of the union of two ranges covered by both
+    fn overlap(a: &Range<f32>, b: &Range<f32>) -> f32 {
+        let both = (a.end.min(b.end) - a.start.max(b.start)).max(0.0);
+        both / (a.end.max(b.end) - a.start.min(b.start))
+    }
+
+    #[test]
+    fn can_fit_hsl_ranges() {
+        let target = HslRange {
+            hue: 35.0..62.0,
+            saturation: 0.3..0.8,
+            lightness: 0.4..0.9,
+        };
+        let mut evaluations = 0;
+        let (range, score) = fit_hsl_range(|range| {
+            evaluations += 1;
+            overlap(&range.hue, &target.hue)
+                * overlap(&range.saturation, &target.saturation)
+                * overlap(&range.lightness, &target.lightness)
+        });
+
+        assert!(score > 0.9, "{range:?}: {score}");
+        assert!((range.hue.start - 35.0).abs() <= 1.0, "{range:?}");
+        assert!((range.hue.end - 62.0).abs() <= 1.0, "{range:?}");
+        assert!((range.saturation.start - 0.3).abs() < 0.02, "{range:?}");
+        assert!((range.lightness.end - 0.9).abs() < 0.02, "{range:?}");
+        assert!(evaluations < 500, "{evaluations} evaluations");
+    }
+
+    #[test]
+    fn matches_empty_labels_without_items() {
+        let database = Database::from_fixture();
+        let names = ["Forma Blueprint", "Formaldehyde"].map(String::from);
+        let items = ["Forma Blueprint", ""].map(String::from);
+        assert_eq!(matched_labels(&database, &names, &items), 2);
+        // Unread rewards only match empty labels
+        assert_eq!(matched_labels(&database, &names[..1], &items), 2);
+        let items = ["Forma Blueprint", "Octavia Prime Systems Blueprint"].map(String::from);
+        assert_eq!(matched_labels(&database, &names, &items), 1);
+    }
+
+    #[test]
+    fn can_load_samples() {
+        let temporary = TempDir::new().unwrap();
+        let directory = temporary.path();
+        RgbImage::from_pixel(4, 4, Rgb([190, 169, 102]))
+            .save(directory.join("1.png"))
+            .unwrap();
+        fs::write(
+            directory.join("labels.json"),
+            r#"{"1.png": {"theme": "Vitruvian", "items": ["Forma Blueprint", "Octavia Prime Systems Blueprint"]}}"#,
+        )
+        .unwrap();
+
+        let samples = load_samples(&directory.join("labels.json")).unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].name, "1.png");
+        assert_eq!(samples[0].image.width(), 4);
+        assert_eq!(samples[0].items[1], "Octavia Prime Systems Blueprint");
+        assert!(load_samples(&directory.join("missing.json")).is_err());
+    }
+}
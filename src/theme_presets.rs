@@ -0,0 +1,197 @@
+use std::{
+    fs::{create_dir_all, read_dir, read_to_string, write},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    theme::{HslRange, Theme},
+    versioned::{self, Versioned},
+};
+
+/// A named custom theme, stored as `<name>.json` in the preset directory
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ThemePreset {
+    pub version: u32,
+    pub name: String,
+    pub range: HslRange<f32>,
+}
+
+impl ThemePreset {
+    pub fn new(name: &str, range: HslRange<f32>) -> ThemePreset {
+        ThemePreset {
+            version: Self::VERSION,
+            name: name.to_owned(),
+            range,
+        }
+    }
+
+    /// `$XDG_CONFIG_HOME/wfinfo/themes`
+    pub fn directory() -> PathBuf {
+        Config::directory().join("themes")
+    }
+
+    pub fn theme(&self) -> Theme {
+        Theme::Custom(self.range.to_ordered())
+    }
+
+    pub fn from_json(text: &str) -> Result<ThemePreset, anyhow::Error> {
+        let preset: ThemePreset = versioned::from_json(text)?;
+        check_name(&preset.name)?;
+        Ok(preset)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Theme presets are always serialisable")
+    }
+
+    /// Reads an exported preset, e.g. one shared by someone else
+    pub fn import(path: &Path) -> Result<ThemePreset, anyhow::Error> {
+        let text =
+            read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_json(&text).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn load(name: &str, directory: &Path) -> Result<ThemePreset, anyhow::Error> {
+        check_name(name)?;
+        let path = directory.join(format!("{name}.json"));
+        let preset = Self::import(&path)?;
+        if preset.name != name {
+            bail!("{} contains the preset {:?}", path.display(), preset.name);
+        }
+        Ok(preset)
+    }
+
+    /// Loads every preset in `directory`, a missing directory contains no presets
+    pub fn load_all(directory: &Path) -> Result<Vec<ThemePreset>, anyhow::Error> {
+        let entries = match read_dir(directory) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut presets = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                presets.push(Self::import(&path)?);
+            }
+        }
+        presets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(presets)
+    }
+
+    /// Writes the preset to `directory`, replacing any preset of the same name
+    pub fn save(&self, directory: &Path) -> Result<PathBuf, anyhow::Error> {
+        check_name(&self.name)?;
+        create_dir_all(directory)?;
+        let path = directory.join(format!("{}.json", self.name));
+        write(&path, self.to_json())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+impl Versioned for ThemePreset {
+    const VERSION: u32 = 1;
+    const KIND: &'static str = "theme preset";
+}
+
+/// Preset names are used as file names, so they can't refer to other directories
+fn check_name(name: &str) -> Result<(), anyhow::Error> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        bail!("Invalid theme preset name {name:?}");
+    }
+    Ok(())
+}
+
+/// Finds a theme by name, presets take precedence over the defined themes
+pub fn resolve_theme(name: &str, directory: &Path) -> Result<Theme, anyhow::Error> {
+    if directory.join(format!("{name}.json")).exists() {
+        return Ok(ThemePreset::load(name, directory)?.theme());
+    }
+    match Theme::defined().into_iter().find(|theme| {
+        theme
+            .name()
+            .is_some_and(|theme_name| theme_name.eq_ignore_ascii_case(name))
+    }) {
+        Some(theme) => Ok(theme),
+        None => bail!(
+            "Unknown theme {name:?}, no preset found in {}",
+            directory.display()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn preset(name: &str) -> ThemePreset {
+        ThemePreset::new(
+            name,
+            HslRange {
+                hue: 40.0..60.0,
+                saturation: 0.3..0.8,
+                lightness: 0.4..0.9,
+            },
+        )
+    }
+
+    #[test]
+    fn can_save_and_load_presets() {
+        let temporary = TempDir::new().unwrap();
+        // Created by the first save
+        let directory = temporary.path().join("themes");
+        assert!(ThemePreset::load_all(&directory).unwrap().is_empty());
+
+        preset("gold").save(&directory).unwrap();
+        preset("amber").save(&directory).unwrap();
+        assert_eq!(
+            ThemePreset::load("gold", &directory).unwrap(),
+            preset("gold")
+        );
+        let names: Vec<_> = ThemePreset::load_all(&directory)
+            .unwrap()
+            .into_iter()
+            .map(|preset| preset.name)
+            .collect();
+        assert_eq!(names, ["amber", "gold"]);
+
+        assert!(ThemePreset::load("missing", &directory).is_err());
+        assert!(preset("../escape").save(&directory).is_err());
+    }
+
+    #[test]
+    fn can_import_exported_presets() {
+        let exported = preset("gold").to_json();
+        assert_eq!(ThemePreset::from_json(&exported).unwrap(), preset("gold"));
+        assert!(
+            ThemePreset::from_json(&exported.replace("\"version\": 1", "\"version\": 2")).is_err()
+        );
+    }
+
+    #[test]
+    fn can_resolve_themes() {
+        let temporary = TempDir::new().unwrap();
+        let directory = temporary.path();
+        preset("gold").save(directory).unwrap();
+        assert_eq!(
+            resolve_theme("gold", directory).unwrap(),
+            preset("gold").theme()
+        );
+        assert_eq!(
+            resolve_theme("highcontrast", directory).unwrap(),
+            Theme::HighContrast
+        );
+        assert!(resolve_theme("missing", directory).is_err());
+    }
+}
@@ -1,29 +1,517 @@
-use std::fs::OpenOptions;
+use std::fs::{self, read_to_string, OpenOptions};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use anyhow::Context;
+use log::{debug, info, warn};
+use reqwest::{
+    blocking::Client,
+    header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+pub const DEFAULT_DATA_URL: &str = "https://api.warframestat.us/wfinfo/";
+
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Where price and item data is loaded from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DataSource {
+    /// Server providing the `prices/` and `filtered_items/` endpoints below this base URL
+    Remote(String),
+    /// Directory containing `prices.json` and `filtered_items.json`
+    Directory(PathBuf),
+}
+
+/// Local copies of the downloaded data files, refreshed once they are older than `max_age`
+#[derive(Clone, Debug)]
+pub struct DataCache {
+    directory: PathBuf,
+    max_age: Duration,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheMetadata {
+    url: Option<String>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// Seconds since the unix epoch at which the file was last confirmed to be up to date
+    fetched_at: u64,
+}
+
+impl Default for DataSource {
+    fn default() -> Self {
+        DataSource::Remote(DEFAULT_DATA_URL.to_string())
+    }
+}
+
+impl DataSource {
+    /// `file://` URLs refer to a local directory, anything else to a server
+    pub fn from_url(url: &str) -> Self {
+        match url.strip_prefix("file://") {
+            Some(path) => DataSource::Directory(PathBuf::from(path)),
+            None => DataSource::Remote(url.to_owned()),
+        }
+    }
+
+    /// Picks the first source given on the command line, in the `WFINFO_MIRROR` or
+    /// `WFINFO_DATA_URL` environment variables or in the config file.
+    ///
+    /// A mirror directory takes precedence over a URL from the same place.
+    pub fn resolve(url: Option<&str>, mirror: Option<&Path>, config: &Config) -> Self {
+        let environment_mirror = std::env::var_os("WFINFO_MIRROR").map(PathBuf::from);
+        let environment_url = std::env::var("WFINFO_DATA_URL").ok();
+        Self::resolve_with(
+            url,
+            mirror,
+            environment_url.as_deref(),
+            environment_mirror.as_deref(),
+            config,
+        )
+    }
+
+    /// Like [`DataSource::resolve`], with the environment variables' values passed in
+    pub fn resolve_with(
+        url: Option<&str>,
+        mirror: Option<&Path>,
+        environment_url: Option<&str>,
+        environment_mirror: Option<&Path>,
+        config: &Config,
+    ) -> Self {
+        let candidates = [
+            (mirror.map(Path::to_path_buf), url.map(str::to_owned)),
+            (
+                environment_mirror.map(Path::to_path_buf),
+                environment_url.map(str::to_owned),
+            ),
+            (config.mirror.clone(), config.data_url.clone()),
+        ];
+
+        candidates
+            .into_iter()
+            .find_map(|candidate| match candidate {
+                (Some(mirror), _) => Some(DataSource::Directory(mirror)),
+                (None, Some(url)) => Some(DataSource::from_url(&url)),
+                (None, None) => None,
+            })
+            .unwrap_or_default()
+    }
+
+    fn endpoint(base: &str, name: &str) -> String {
+        format!("{}/{name}/", base.trim_end_matches('/'))
+    }
+}
+
+impl Default for DataCache {
+    fn default() -> Self {
+        Self::new(Self::default_directory(), DEFAULT_MAX_AGE)
+    }
+}
+
+impl DataCache {
+    pub fn new(directory: PathBuf, max_age: Duration) -> Self {
+        Self { directory, max_age }
+    }
+
+    /// `$XDG_CACHE_HOME/wfinfo`, falling back to `~/.cache/wfinfo`
+    pub fn default_directory() -> PathBuf {
+        std::env::var_os("XDG_CACHE_HOME")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(std::env::temp_dir)
+            .join("wfinfo")
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Returns the cached copy of `url` if it is fresh and from the same URL, otherwise downloads it.
+    ///
+    /// If the download fails, the last good copy is used if there is one from the same URL.
+    pub fn fetch(&self, url: &str, filename: &str) -> Result<PathBuf, anyhow::Error> {
+        let path = self.directory.join(filename);
+        if path.exists() && self.is_fresh(url, filename) {
+            debug!("Using cached {}", path.display());
+            return Ok(path);
+        }
+
+        match self.download(url, filename) {
+            Ok(path) => Ok(path),
+            Err(err) if path.exists() => {
+                let cached_url = self
+                    .read_metadata(filename)
+                    .and_then(|metadata| metadata.url);
+                if cached_url.as_deref() != Some(url) {
+                    return Err(err.context(format!(
+                        "Failed to download {url}, the cached {filename} is from {} instead",
+                        cached_url.as_deref().unwrap_or("an unknown URL")
+                    )));
+                }
+                warn!("Failed to update {filename}, using last downloaded copy: {err:#}");
+                Ok(path)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Checks `url` for a newer version regardless of the cached copy's age
+    pub fn download(&self, url: &str, filename: &str) -> Result<PathBuf, anyhow::Error> {
+        let path = self.directory.join(filename);
+        let mut metadata = match self.read_metadata(filename) {
+            Some(metadata) if path.exists() && metadata.url.as_deref() == Some(url) => metadata,
+            _ => CacheMetadata::default(),
+        };
+
+        let mut request = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?
+            .get(url);
+        if let Some(etag) = metadata.etag.as_ref() {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = metadata.last_modified.as_ref() {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+
+        let response = request.send()?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            info!("{filename} is up to date");
+        } else {
+            let response = response.error_for_status()?;
+            let header = |name| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_owned)
+            };
+            metadata.etag = header(ETAG);
+            metadata.last_modified = header(LAST_MODIFIED);
+
+            let body = response.bytes()?;
+            serde_json::from_slice::<serde_json::Value>(&body)
+                .with_context(|| format!("Downloaded {filename} is not valid JSON"))?;
+            write_atomically(&path, &body)?;
+            info!("Downloaded {filename}");
+        }
+
+        metadata.url = Some(url.to_owned());
+        metadata.fetched_at = unix_time_now();
+        write_atomically(
+            &self.metadata_path(filename),
+            &serde_json::to_vec(&metadata)?,
+        )?;
+
+        Ok(path)
+    }
+
+    pub fn fetch_prices_and_items(
+        &self,
+        source: &DataSource,
+    ) -> Result<(PathBuf, PathBuf), anyhow::Error> {
+        match source {
+            DataSource::Remote(base) => {
+                let prices = self.fetch(&DataSource::endpoint(base, "prices"), "prices.json")?;
+                let items = self.fetch(
+                    &DataSource::endpoint(base, "filtered_items"),
+                    "filtered_items.json",
+                )?;
+                Ok((prices, items))
+            }
+            DataSource::Directory(directory) => directory_prices_and_items(directory),
+        }
+    }
+
+    pub fn update_prices_and_items(
+        &self,
+        source: &DataSource,
+    ) -> Result<(PathBuf, PathBuf), anyhow::Error> {
+        match source {
+            DataSource::Remote(base) => {
+                let prices = self.download(&DataSource::endpoint(base, "prices"), "prices.json")?;
+                let items = self.download(
+                    &DataSource::endpoint(base, "filtered_items"),
+                    "filtered_items.json",
+                )?;
+                Ok((prices, items))
+            }
+            DataSource::Directory(directory) => directory_prices_and_items(directory),
+        }
+    }
+
+    fn is_fresh(&self, url: &str, filename: &str) -> bool {
+        self.read_metadata(filename).is_some_and(|metadata| {
+            metadata.url.as_deref() == Some(url)
+                && unix_time_now().saturating_sub(metadata.fetched_at) < self.max_age.as_secs()
+        })
+    }
+
+    fn metadata_path(&self, filename: &str) -> PathBuf {
+        self.directory.join(format!("{filename}.meta.json"))
+    }
+
+    fn read_metadata(&self, filename: &str) -> Option<CacheMetadata> {
+        let text = read_to_string(self.metadata_path(filename)).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+}
+
+/// Fetches the data from the source and with the cache settings given in the config file
 pub fn fetch_prices_and_items() -> Result<(PathBuf, PathBuf), anyhow::Error> {
-    let prices = download_and_save("https://api.warframestat.us/wfinfo/prices/", "prices.json")?;
-    let items = download_and_save(
-        "https://api.warframestat.us/wfinfo/filtered_items/",
-        "filtered_items.json",
-    )?;
-    Ok((prices, items))
+    let config = Config::load()?;
+    let source = DataSource::resolve(None, None, &config);
+    DataCache::new(DataCache::default_directory(), config.cache_max_age())
+        .fetch_prices_and_items(&source)
 }
 
-fn download_and_save(url: &str, filename: &str) -> Result<PathBuf, anyhow::Error> {
-    let path = std::env::temp_dir().join(filename);
-    if path.exists() {
-        return Ok(path);
+fn directory_prices_and_items(directory: &Path) -> Result<(PathBuf, PathBuf), anyhow::Error> {
+    let prices = directory.join("prices.json");
+    let items = directory.join("filtered_items.json");
+    for path in [&prices, &items] {
+        if !path.is_file() {
+            anyhow::bail!("Missing data file {}", path.display());
+        }
     }
+    Ok((prices, items))
+}
+
+/// Writes to a temporary file next to `path` first, so readers never see a partial file
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), anyhow::Error> {
+    let directory = path.parent().context("Path has no parent directory")?;
+    fs::create_dir_all(directory)?;
+
+    let mut temporary_name = path.file_name().unwrap_or_default().to_owned();
+    temporary_name.push(format!(".{}.tmp", std::process::id()));
+    let temporary_path = directory.join(temporary_name);
 
-    let res = reqwest::blocking::get(url)?;
     let mut file = OpenOptions::new()
         .write(true)
         .truncate(true)
         .create(true)
-        .open(&path)?;
-    file.write_all(res.text()?.as_bytes())?;
+        .open(&temporary_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&temporary_path, path)?;
+
+    Ok(())
+}
+
+fn unix_time_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Minimal HTTP server answering each connection with the next of the given responses.
+    /// Returns its address and the request headers it received.
+    fn serve(responses: Vec<(u16, &'static str)>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}/data/", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+
+        thread::spawn(move || {
+            for (status, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    request.push_str(&line);
+                }
+                received.lock().unwrap().push(request);
+
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} Status\r\nETag: \"v1\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+
+        (address, requests)
+    }
+
+    #[test]
+    fn downloads_and_reuses_fresh_copy() {
+        let (url, requests) = serve(vec![(200, "[1]")]);
+        let directory = TempDir::new().unwrap();
+        let cache = DataCache::new(directory.path().to_owned(), Duration::from_secs(60));
+
+        let path = cache.fetch(&url, "data.json").unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "[1]");
+
+        // The server only answers once, a second request would fail
+        let path = cache.fetch(&url, "data.json").unwrap();
+        assert_eq!(read_to_string(path).unwrap(), "[1]");
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn revalidates_stale_copy_with_etag() {
+        let (url, requests) = serve(vec![(200, "[1]"), (304, "")]);
+        let directory = TempDir::new().unwrap();
+        let cache = DataCache::new(directory.path().to_owned(), Duration::ZERO);
 
-    Ok(path)
+        cache.fetch(&url, "data.json").unwrap();
+        let path = cache.fetch(&url, "data.json").unwrap();
+        assert_eq!(read_to_string(path).unwrap(), "[1]");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0].to_lowercase().contains("if-none-match"));
+        assert!(requests[1].to_lowercase().contains("if-none-match: \"v1\""));
+    }
+
+    #[test]
+    fn falls_back_to_last_good_copy() {
+        let (url, _requests) = serve(vec![(200, "[1]"), (500, ""), (200, "not json")]);
+        let directory = TempDir::new().unwrap();
+        let cache = DataCache::new(directory.path().to_owned(), Duration::ZERO);
+
+        cache.fetch(&url, "data.json").unwrap();
+        let path = cache.fetch(&url, "data.json").unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "[1]");
+        let path = cache.fetch(&url, "data.json").unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "[1]");
+
+        assert!(cache.download(&url, "data.json").is_err());
+    }
+
+    #[test]
+    fn refetches_when_url_changes() {
+        let (first_url, _requests) = serve(vec![(200, "[1]")]);
+        let (second_url, _requests) = serve(vec![(200, "[2]")]);
+        let directory = TempDir::new().unwrap();
+        let cache = DataCache::new(directory.path().to_owned(), Duration::from_secs(60));
+
+        cache.fetch(&first_url, "data.json").unwrap();
+        let path = cache.fetch(&second_url, "data.json").unwrap();
+        assert_eq!(read_to_string(path).unwrap(), "[2]");
+    }
+
+    #[test]
+    fn does_not_fall_back_to_copy_from_other_url() {
+        let (first_url, _requests) = serve(vec![(200, "[1]")]);
+        let (second_url, _requests) = serve(vec![(500, "")]);
+        let directory = TempDir::new().unwrap();
+        let cache = DataCache::new(directory.path().to_owned(), Duration::from_secs(60));
+
+        cache.fetch(&first_url, "data.json").unwrap();
+        let err = cache.fetch(&second_url, "data.json").unwrap_err();
+        assert!(format!("{err:#}").contains(&first_url));
+    }
+
+    #[test]
+    fn loads_from_remote_endpoints() {
+        let (url, requests) = serve(vec![(200, "[]"), (200, "{}")]);
+        let base = url.trim_end_matches("data/").to_string() + "mirror";
+        let directory = TempDir::new().unwrap();
+        let cache = DataCache::new(directory.path().to_owned(), Duration::from_secs(60));
+
+        let (prices, items) = cache
+            .fetch_prices_and_items(&DataSource::from_url(&base))
+            .unwrap();
+        assert_eq!(read_to_string(prices).unwrap(), "[]");
+        assert_eq!(read_to_string(items).unwrap(), "{}");
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].starts_with("GET /mirror/prices/ "));
+        assert!(requests[1].starts_with("GET /mirror/filtered_items/ "));
+    }
+
+    #[test]
+    fn loads_from_directory() {
+        let temporary = TempDir::new().unwrap();
+        let directory = temporary.path().join("mirror");
+        fs::create_dir_all(&directory).unwrap();
+        let cache = DataCache::new(temporary.path().join("unused"), Duration::ZERO);
+        let source = DataSource::from_url(&format!("file://{}", directory.display()));
+        assert_eq!(source, DataSource::Directory(directory.clone()));
+
+        assert!(cache.fetch_prices_and_items(&source).is_err());
+
+        fs::write(directory.join("prices.json"), "[]").unwrap();
+        fs::write(directory.join("filtered_items.json"), "{}").unwrap();
+        let (prices, items) = cache.fetch_prices_and_items(&source).unwrap();
+        assert_eq!(prices, directory.join("prices.json"));
+        assert_eq!(items, directory.join("filtered_items.json"));
+        assert!(!cache.directory().exists());
+    }
+
+    #[test]
+    fn resolves_source_by_precedence() {
+        let config = Config {
+            data_url: Some("https://example.com/wfinfo".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            DataSource::resolve_with(None, None, None, None, &config),
+            DataSource::Remote("https://example.com/wfinfo".to_string())
+        );
+        assert_eq!(
+            DataSource::resolve_with(Some("file:///srv/wfinfo"), None, None, None, &config),
+            DataSource::Directory(PathBuf::from("/srv/wfinfo"))
+        );
+        assert_eq!(
+            DataSource::resolve_with(
+                Some("https://example.org"),
+                Some(Path::new("snapshot")),
+                None,
+                None,
+                &config
+            ),
+            DataSource::Directory(PathBuf::from("snapshot"))
+        );
+        assert_eq!(
+            DataSource::resolve_with(None, None, None, Some(Path::new("environment")), &config),
+            DataSource::Directory(PathBuf::from("environment"))
+        );
+        assert_eq!(
+            DataSource::resolve_with(
+                Some("https://example.org"),
+                None,
+                Some("https://example.net"),
+                None,
+                &config
+            ),
+            DataSource::Remote("https://example.org".to_string())
+        );
+        assert_eq!(
+            DataSource::resolve_with(None, None, None, None, &Config::default()),
+            DataSource::default()
+        );
+    }
+
+    #[test]
+    fn fails_without_any_copy() {
+        let (url, _requests) = serve(vec![(500, "")]);
+        let directory = TempDir::new().unwrap();
+        let cache = DataCache::new(directory.path().to_owned(), Duration::ZERO);
+
+        assert!(cache.fetch(&url, "data.json").is_err());
+        assert!(!cache.directory().join("data.json").exists());
+    }
 }
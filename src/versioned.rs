@@ -0,0 +1,59 @@
+use anyhow::bail;
+use serde::{de::DeserializeOwned, Deserialize};
+
+/// A JSON data format with a top level `version` field
+pub trait Versioned: DeserializeOwned {
+    /// Version of the format understood by this build
+    const VERSION: u32;
+    /// What the data is, for error messages, e.g. "reward catalogue"
+    const KIND: &'static str;
+}
+
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+/// Parses `text` after checking its version, so files of other versions fail with a clear message
+pub fn from_json<T: Versioned>(text: &str) -> Result<T, anyhow::Error> {
+    let header: Header = serde_json::from_str(text)?;
+    if header.version != T::VERSION {
+        bail!(
+            "Unsupported {} version {}, expected {}",
+            T::KIND,
+            header.version,
+            T::VERSION
+        );
+    }
+    Ok(serde_json::from_str(text)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Data {
+        version: u32,
+        names: Vec<String>,
+    }
+
+    impl Versioned for Data {
+        const VERSION: u32 = 2;
+        const KIND: &'static str = "test data";
+    }
+
+    #[test]
+    fn checks_versions() {
+        let data: Data = from_json(r#"{"version": 2, "names": ["Forma"]}"#).unwrap();
+        assert_eq!(data.names, ["Forma"]);
+
+        // The version is checked before the rest of the format
+        let err = from_json::<Data>(r#"{"version": 1, "items": []}"#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unsupported test data version 1, expected 2"
+        );
+        assert!(from_json::<Data>(r#"{"names": []}"#).is_err());
+    }
+}
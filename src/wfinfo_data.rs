@@ -1,5 +1,5 @@
-use serde::Deserialize;
-use serde_aux::prelude::deserialize_number_from_string;
+use serde::{Deserialize, Serialize};
+use serde_aux::prelude::{deserialize_number_from_string, deserialize_option_number_from_string};
 
 pub mod price_data {
     use super::*;
@@ -9,6 +9,10 @@ pub mod price_data {
         pub name: String,
         #[serde(deserialize_with = "deserialize_number_from_string")]
         pub custom_avg: f32,
+        #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+        pub yesterday_vol: Option<usize>,
+        #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+        pub today_vol: Option<usize>,
     }
 }
 
@@ -21,9 +25,15 @@ pub mod item_data {
     pub struct DucatItem {
         #[serde(default)]
         pub ducats: usize,
+        #[serde(default = "default_count")]
+        pub count: usize,
     }
 
-    #[derive(Clone, Debug, Deserialize)]
+    fn default_count() -> usize {
+        1
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
     pub enum EquipmentType {
         Warframes,
         Primary,
@@ -52,7 +62,22 @@ pub mod item_data {
         Radiant,
     }
 
-    #[derive(Clone, Debug, Deserialize)]
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Rarity {
+        Common,
+        Uncommon,
+        Rare,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub enum RelicTier {
+        Lith,
+        Meso,
+        Neo,
+        Axi,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
     pub struct Relic {
         pub vaulted: bool,
         pub rare1: String,
@@ -63,7 +88,7 @@ pub mod item_data {
         pub common3: String,
     }
 
-    #[derive(Clone, Debug, Deserialize)]
+    #[derive(Clone, Debug, Default, Serialize, Deserialize)]
     pub struct Relics {
         #[serde(rename = "Lith")]
         pub lith: HashMap<String, Relic>,
@@ -84,6 +109,13 @@ pub mod item_data {
     }
 
     impl Refinement {
+        pub const ALL: [Refinement; 4] = [
+            Refinement::Intact,
+            Refinement::Exceptional,
+            Refinement::Flawless,
+            Refinement::Radiant,
+        ];
+
         pub fn common_chance(&self) -> f32 {
             match self {
                 Refinement::Intact => 0.2533,
@@ -111,4 +143,63 @@ pub mod item_data {
             }
         }
     }
+
+    impl Rarity {
+        pub fn chance(&self, refinement: Refinement) -> f32 {
+            match self {
+                Rarity::Common => refinement.common_chance(),
+                Rarity::Uncommon => refinement.uncommon_chance(),
+                Rarity::Rare => refinement.rare_chance(),
+            }
+        }
+    }
+
+    impl Relic {
+        /// All six reward slots of this relic along with their rarity
+        pub fn drops(&self) -> [(&str, Rarity); 6] {
+            [
+                (&self.common1, Rarity::Common),
+                (&self.common2, Rarity::Common),
+                (&self.common3, Rarity::Common),
+                (&self.uncommon1, Rarity::Uncommon),
+                (&self.uncommon2, Rarity::Uncommon),
+                (&self.rare1, Rarity::Rare),
+            ]
+        }
+    }
+
+    impl Relics {
+        pub fn tier(&self, tier: RelicTier) -> &HashMap<String, Relic> {
+            match tier {
+                RelicTier::Lith => &self.lith,
+                RelicTier::Meso => &self.meso,
+                RelicTier::Neo => &self.neo,
+                RelicTier::Axi => &self.axi,
+            }
+        }
+
+        pub fn tier_mut(&mut self, tier: RelicTier) -> &mut HashMap<String, Relic> {
+            match tier {
+                RelicTier::Lith => &mut self.lith,
+                RelicTier::Meso => &mut self.meso,
+                RelicTier::Neo => &mut self.neo,
+                RelicTier::Axi => &mut self.axi,
+            }
+        }
+
+        pub fn iter(&self) -> impl Iterator<Item = (RelicTier, &String, &Relic)> {
+            [
+                RelicTier::Lith,
+                RelicTier::Meso,
+                RelicTier::Neo,
+                RelicTier::Axi,
+            ]
+            .into_iter()
+            .flat_map(move |tier| {
+                self.tier(tier)
+                    .iter()
+                    .map(move |(name, relic)| (tier, name, relic))
+            })
+        }
+    }
 }